@@ -0,0 +1,64 @@
+// Copyright 2013-2014 Jeffery Olson
+//
+// Licensed under the 3-Clause BSD License, see LICENSE.txt
+// at the top-level of this repository.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fmt;
+use std::result::Result;
+
+#[deriving(Clone, PartialEq)]
+pub enum P2duxError {
+    WindowCreation(String),
+    RendererCreation(String),
+    // path, sdl message
+    ImageLoad(String, String),
+    // path, sdl message
+    ImageSave(String, String),
+    MissingSheet(String),
+    MissingGlyph(char),
+    RenderCall(String),
+    // a scripted InputSource ran out of frames before the view returned
+    InputExhausted,
+    // a window operation on a headless display
+    NoWindow,
+    // path, io error message
+    Io(String, String),
+    Config(String),
+    // a SheetLoader's worker went away before decoding everything
+    LoaderDisconnected
+}
+
+pub type P2duxResult<T> = Result<T, P2duxError>;
+
+impl fmt::Show for P2duxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            P2duxError::WindowCreation(ref msg) =>
+                write!(f, "failed to create window: {}", msg),
+            P2duxError::RendererCreation(ref msg) =>
+                write!(f, "failed to create renderer: {}", msg),
+            P2duxError::ImageLoad(ref path, ref msg) =>
+                write!(f, "couldn't load image '{}': {}", path, msg),
+            P2duxError::ImageSave(ref path, ref msg) =>
+                write!(f, "couldn't save image '{}': {}", path, msg),
+            P2duxError::MissingSheet(ref name) =>
+                write!(f, "no sprite sheet named '{}'", name),
+            P2duxError::MissingGlyph(c) =>
+                write!(f, "no font sprite for '{}'", c),
+            P2duxError::RenderCall(ref msg) =>
+                write!(f, "render call failed: {}", msg),
+            P2duxError::InputExhausted =>
+                write!(f, "scripted input ran out of frames"),
+            P2duxError::NoWindow =>
+                write!(f, "display has no window"),
+            P2duxError::Io(ref path, ref msg) =>
+                write!(f, "io error on '{}': {}", path, msg),
+            P2duxError::Config(ref msg) =>
+                write!(f, "bad display config: {}", msg),
+            P2duxError::LoaderDisconnected =>
+                write!(f, "sheet loader stopped before finishing")
+        }
+    }
+}
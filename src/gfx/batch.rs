@@ -0,0 +1,153 @@
+// Copyright 2013-2014 Jeffery Olson
+//
+// Licensed under the 3-Clause BSD License, see LICENSE.txt
+// at the top-level of this repository.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::vec::Vec;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use sdl2::rect::Rect;
+use sdl2::pixels::Color;
+
+pub enum DrawCommand {
+    // sheet name, src rect on the sheet, dst rect on screen
+    Tile(String, Rect, Rect),
+    Fill(Color, Rect)
+}
+
+pub struct QueuedDraw {
+    pub layer: uint,
+    // which stretch of the layer between fills this falls in
+    pub run: uint,
+    pub seq: uint,
+    pub cmd: DrawCommand
+}
+
+// Draws collected while GameDisplay is batching. They are flushed ordered by
+// layer. Within a layer, fills stay exactly where they were submitted, so a
+// box's background still covers whatever was drawn on the layer before it;
+// only the tiles between two fills are regrouped by sheet, so each texture
+// is bound as few times as possible. Otherwise submission order only breaks
+// ties, so anything that has to be drawn over something else on the same
+// sheet should go on a higher layer.
+pub struct DrawQueue {
+    items: Vec<QueuedDraw>,
+    next_seq: uint,
+    // current run for each layer
+    runs: HashMap<uint, uint>
+}
+
+impl DrawQueue {
+    pub fn new() -> DrawQueue {
+        DrawQueue { items: Vec::new(), next_seq: 0, runs: HashMap::new() }
+    }
+
+    pub fn push(&mut self, layer: uint, cmd: DrawCommand) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let current = match self.runs.get(&layer) {
+            Some(run) => *run,
+            None => 0
+        };
+        // a fill gets a run to itself, and tiles after it start another
+        let (run, next) = match cmd {
+            DrawCommand::Fill(..) => (current + 1, current + 2),
+            DrawCommand::Tile(..) => (current, current)
+        };
+        self.runs.insert(layer, next);
+        self.items.push(QueuedDraw { layer: layer, run: run, seq: seq, cmd: cmd });
+    }
+
+    pub fn len(&self) -> uint { self.items.len() }
+    pub fn is_empty(&self) -> bool { self.items.is_empty() }
+
+    // empties the queue, handing back its contents in draw order
+    pub fn take_sorted(&mut self) -> Vec<QueuedDraw> {
+        let mut items = Vec::new();
+        ::std::mem::swap(&mut items, &mut self.items);
+        self.next_seq = 0;
+        self.runs.clear();
+        items.sort_by(draw_order);
+        items
+    }
+}
+
+fn draw_order(a: &QueuedDraw, b: &QueuedDraw) -> Ordering {
+    match a.layer.cmp(&b.layer) {
+        Ordering::Equal => {},
+        o => return o
+    }
+    match a.run.cmp(&b.run) {
+        Ordering::Equal => {},
+        o => return o
+    }
+    // a run is either a single fill or nothing but tiles
+    let by_sheet = match (&a.cmd, &b.cmd) {
+        (&DrawCommand::Tile(ref a_sheet, _, _), &DrawCommand::Tile(ref b_sheet, _, _)) =>
+            a_sheet.cmp(b_sheet),
+        _ => Ordering::Equal
+    };
+    match by_sheet {
+        Ordering::Equal => a.seq.cmp(&b.seq),
+        o => o
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use sdl2::rect::Rect;
+    use sdl2::pixels::Color;
+
+    use super::{DrawQueue, DrawCommand, QueuedDraw};
+
+    fn tile(sheet: &str) -> DrawCommand {
+        DrawCommand::Tile(sheet.to_string(), Rect::new(0, 0, 8, 8), Rect::new(0, 0, 8, 8))
+    }
+
+    fn fill() -> DrawCommand {
+        DrawCommand::Fill(Color::RGB(0, 0, 0), Rect::new(0, 0, 8, 8))
+    }
+
+    // submission order of each item as drawn
+    fn seqs(items: &[QueuedDraw]) -> Vec<uint> {
+        items.iter().map(|item| item.seq).collect()
+    }
+
+    #[test]
+    fn fills_stay_between_the_tiles_around_them() {
+        let mut queue = DrawQueue::new();
+        queue.push(0, fill());
+        queue.push(0, tile("b"));
+        queue.push(0, tile("a"));
+        queue.push(0, fill());
+        queue.push(0, tile("a"));
+        let items = queue.take_sorted();
+        assert_eq!(seqs(items.as_slice()), vec![0, 2, 1, 3, 4]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn tiles_in_a_run_are_grouped_by_sheet() {
+        let mut queue = DrawQueue::new();
+        queue.push(0, tile("b"));
+        queue.push(0, tile("a"));
+        queue.push(0, tile("b"));
+        queue.push(0, tile("a"));
+        let items = queue.take_sorted();
+        assert_eq!(seqs(items.as_slice()), vec![1, 3, 0, 2]);
+    }
+
+    #[test]
+    fn layers_come_before_runs() {
+        let mut queue = DrawQueue::new();
+        queue.push(1, tile("a"));
+        queue.push(0, fill());
+        queue.push(1, fill());
+        queue.push(0, tile("a"));
+        let items = queue.take_sorted();
+        assert_eq!(seqs(items.as_slice()), vec![1, 3, 0, 2]);
+    }
+}
@@ -0,0 +1,169 @@
+// Copyright 2013-2014 Jeffery Olson
+//
+// Licensed under the 3-Clause BSD License, see LICENSE.txt
+// at the top-level of this repository.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::rand::random;
+
+// Maps world coordinates (pixels at zoom 1.0) to screen coordinates. The
+// camera's position is the world point shown at the center of its
+// viewport, which is `viewport_size` screen pixels with its top-left at
+// `viewport_origin`.
+pub struct Camera {
+    position: (f64, f64),
+    zoom: f64,
+    viewport_origin: (int, int),
+    viewport_size: (uint, uint),
+    // world-space (x, y, w, h) the view is kept inside of
+    bounds: Option<(f64, f64, f64, f64)>,
+    follow_target: Option<(f64, f64)>,
+    // fraction of the remaining distance to the target covered per second
+    follow_rate: f64,
+    shake_intensity: f64,
+    shake_duration_ms: u64,
+    shake_remaining_ms: u64,
+    shake_offset: (f64, f64)
+}
+
+impl Camera {
+    pub fn new(viewport_size: (uint, uint)) -> Camera {
+        Camera {
+            position: (0.0, 0.0),
+            zoom: 1.0,
+            viewport_origin: (0, 0),
+            viewport_size: viewport_size,
+            bounds: None,
+            follow_target: None,
+            follow_rate: 0.9,
+            shake_intensity: 0.0,
+            shake_duration_ms: 0,
+            shake_remaining_ms: 0,
+            shake_offset: (0.0, 0.0)
+        }
+    }
+
+    pub fn get_position(&self) -> (f64, f64) { self.position }
+    pub fn set_position(&mut self, position: (f64, f64)) {
+        self.position = position;
+        self.clamp_to_bounds();
+    }
+
+    pub fn get_zoom(&self) -> f64 { self.zoom }
+    pub fn set_zoom(&mut self, zoom: f64) {
+        self.zoom = zoom.max(0.01);
+        self.clamp_to_bounds();
+    }
+
+    pub fn get_viewport(&self) -> ((int, int), (uint, uint)) {
+        (self.viewport_origin, self.viewport_size)
+    }
+    pub fn set_viewport(&mut self, origin: (int, int), size: (uint, uint)) {
+        self.viewport_origin = origin;
+        self.viewport_size = size;
+        self.clamp_to_bounds();
+    }
+
+    pub fn set_bounds(&mut self, bounds: Option<(f64, f64, f64, f64)>) {
+        self.bounds = bounds;
+        self.clamp_to_bounds();
+    }
+
+    // `rate` is the fraction (0.0 to 1.0) of the remaining distance covered
+    // each second; 1.0 snaps straight to the target
+    pub fn follow(&mut self, target: Option<(f64, f64)>, rate: f64) {
+        self.follow_target = target;
+        self.follow_rate = rate.max(0.0).min(1.0);
+    }
+
+    pub fn shake(&mut self, intensity: f64, duration_ms: u64) {
+        self.shake_intensity = intensity;
+        self.shake_duration_ms = duration_ms;
+        self.shake_remaining_ms = duration_ms;
+    }
+
+    // advance following and shaking by `dt_ms`
+    pub fn update(&mut self, dt_ms: u64) {
+        match self.follow_target {
+            Some((tx, ty)) => {
+                let (x, y) = self.position;
+                let t = if self.follow_rate >= 1.0 {
+                    1.0
+                } else {
+                    1.0 - (1.0 - self.follow_rate).powf(dt_ms as f64 / 1000.0)
+                };
+                self.position = (x + (tx - x) * t, y + (ty - y) * t);
+                self.clamp_to_bounds();
+            },
+            None => {}
+        }
+        if self.shake_remaining_ms > 0 && self.shake_duration_ms > 0 {
+            self.shake_remaining_ms = if dt_ms >= self.shake_remaining_ms { 0 }
+                else { self.shake_remaining_ms - dt_ms };
+            // decays linearly over the shake's duration
+            let strength = self.shake_intensity *
+                (self.shake_remaining_ms as f64 / self.shake_duration_ms as f64);
+            self.shake_offset = ((random::<f64>() * 2.0 - 1.0) * strength,
+                                 (random::<f64>() * 2.0 - 1.0) * strength);
+        } else {
+            self.shake_offset = (0.0, 0.0);
+        }
+    }
+
+    // size of the visible area in world units
+    pub fn get_visible_size(&self) -> (f64, f64) {
+        let (vw, vh) = self.viewport_size;
+        (vw as f64 / self.zoom, vh as f64 / self.zoom)
+    }
+
+    pub fn world_to_screen(&self, world: (f64, f64)) -> (int, int) {
+        let (wx, wy) = world;
+        let (cx, cy) = self.position;
+        let (sx, sy) = self.shake_offset;
+        let (vx, vy) = self.viewport_origin;
+        let (vw, vh) = self.viewport_size;
+        let x = (wx - cx + sx) * self.zoom + vw as f64 / 2.0;
+        let y = (wy - cy + sy) * self.zoom + vh as f64 / 2.0;
+        (vx + x.floor() as int, vy + y.floor() as int)
+    }
+
+    pub fn screen_to_world(&self, screen: (int, int)) -> (f64, f64) {
+        let (x, y) = screen;
+        let (cx, cy) = self.position;
+        let (sx, sy) = self.shake_offset;
+        let (vx, vy) = self.viewport_origin;
+        let (vw, vh) = self.viewport_size;
+        ((((x - vx) as f64) - vw as f64 / 2.0) / self.zoom + cx - sx,
+         (((y - vy) as f64) - vh as f64 / 2.0) / self.zoom + cy - sy)
+    }
+
+    // whether any part of the world-space rect (x, y, w, h) is on screen
+    pub fn is_visible(&self, rect: (f64, f64, f64, f64)) -> bool {
+        let (x, y, w, h) = rect;
+        let (cx, cy) = self.position;
+        let (vis_w, vis_h) = self.get_visible_size();
+        let (left, top) = (cx - vis_w / 2.0, cy - vis_h / 2.0);
+        x < left + vis_w && x + w > left && y < top + vis_h && y + h > top
+    }
+
+    fn clamp_to_bounds(&mut self) {
+        let (bx, by, bw, bh) = match self.bounds {
+            Some(b) => b,
+            None => return
+        };
+        let (vis_w, vis_h) = self.get_visible_size();
+        let (x, y) = self.position;
+        self.position = (clamp_axis(x, bx, bw, vis_w), clamp_axis(y, by, bh, vis_h));
+    }
+}
+
+// keep a view of `visible` length centered on `pos` inside [start, start+len],
+// centering on the bounds when they're smaller than the view
+fn clamp_axis(pos: f64, start: f64, len: f64, visible: f64) -> f64 {
+    if len <= visible {
+        return start + len / 2.0;
+    }
+    let (lo, hi) = (start + visible / 2.0, start + len - visible / 2.0);
+    pos.max(lo).min(hi)
+}
@@ -0,0 +1,122 @@
+// Copyright 2013-2014 Jeffery Olson
+//
+// Licensed under the 3-Clause BSD License, see LICENSE.txt
+// at the top-level of this repository.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::io::File;
+use serialize::json;
+
+use error::{P2duxError, P2duxResult};
+use super::window::WindowMode;
+
+#[deriving(Clone, PartialEq, Show, Encodable, Decodable)]
+pub enum WindowPosition {
+    Centered,
+    Undefined,
+    At(int, int)
+}
+
+#[deriving(Clone, PartialEq, Show, Encodable, Decodable)]
+pub enum RendererBackend {
+    Accelerated,
+    Software
+}
+
+// Everything GameDisplay::from_config() needs to open a window. Build one
+// up from DisplayConfig::new(), e.g.
+//
+//     DisplayConfig::new("my game", (1280, 720)).vsync(true).logical_size(Some((320, 180)))
+//
+// It round-trips through json, so players' settings can be kept on disk.
+#[deriving(Clone, PartialEq, Show, Encodable, Decodable)]
+pub struct DisplayConfig {
+    pub title: String,
+    pub size: (int, int),
+    pub position: WindowPosition,
+    pub window_mode: WindowMode,
+    pub resizable: bool,
+    pub vsync: bool,
+    pub backend: RendererBackend,
+    pub logical_size: Option<(uint, uint)>,
+    // color around the scaled logical image; black when None
+    pub letterbox_color: Option<(u8, u8, u8)>,
+    pub icon_path: Option<String>,
+    pub min_size: Option<(int, int)>
+}
+
+impl DisplayConfig {
+    // centered, windowed, accelerated and without vsync
+    pub fn new(title: &str, size: (int, int)) -> DisplayConfig {
+        DisplayConfig {
+            title: title.to_string(),
+            size: size,
+            position: WindowPosition::Centered,
+            window_mode: WindowMode::Windowed,
+            resizable: false,
+            vsync: false,
+            backend: RendererBackend::Accelerated,
+            logical_size: None,
+            letterbox_color: None,
+            icon_path: None,
+            min_size: None
+        }
+    }
+
+    pub fn position(mut self, position: WindowPosition) -> DisplayConfig {
+        self.position = position;
+        self
+    }
+    pub fn window_mode(mut self, mode: WindowMode) -> DisplayConfig {
+        self.window_mode = mode;
+        self
+    }
+    pub fn resizable(mut self, resizable: bool) -> DisplayConfig {
+        self.resizable = resizable;
+        self
+    }
+    pub fn vsync(mut self, vsync: bool) -> DisplayConfig {
+        self.vsync = vsync;
+        self
+    }
+    pub fn backend(mut self, backend: RendererBackend) -> DisplayConfig {
+        self.backend = backend;
+        self
+    }
+    pub fn logical_size(mut self, size: Option<(uint, uint)>) -> DisplayConfig {
+        self.logical_size = size;
+        self
+    }
+    pub fn letterbox_color(mut self, rgb: Option<(u8, u8, u8)>) -> DisplayConfig {
+        self.letterbox_color = rgb;
+        self
+    }
+    pub fn icon_path(mut self, path: Option<String>) -> DisplayConfig {
+        self.icon_path = path;
+        self
+    }
+    pub fn min_size(mut self, size: Option<(int, int)>) -> DisplayConfig {
+        self.min_size = size;
+        self
+    }
+
+    pub fn save(&self, path: &Path) -> P2duxResult<()> {
+        let encoded = json::encode(self);
+        match File::create(path).write_str(encoded.as_slice()) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(P2duxError::Io(path.display().to_string(), e.to_string()))
+        }
+    }
+
+    pub fn load(path: &Path) -> P2duxResult<DisplayConfig> {
+        let contents = match File::open(path).read_to_string() {
+            Ok(c) => c,
+            Err(e) => return Err(P2duxError::Io(path.display().to_string(), e.to_string()))
+        };
+        match json::decode(contents.as_slice()) {
+            Ok(config) => Ok(config),
+            Err(e) => Err(P2duxError::Config(format!("couldn't read '{}': {}", path.display(), e)))
+        }
+    }
+}
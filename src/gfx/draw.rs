@@ -8,26 +8,54 @@
 use std::option::{Some, None};
 
 use super::GameDisplay;
+use super::layer;
+use super::camera::Camera;
 use p2d::sprite::SpriteTile;
 
+use error::P2duxResult;
+
 pub trait DrawableItem {
     fn get_sprites<'a>(&'a self) -> &'a [SpriteTile];
+    fn get_layer(&self) -> uint { layer::ENTITIES }
 
     fn draw(&self, display: &GameDisplay,
-                          base: (int, int), offset: (int, int)) {
+                          base: (int, int), offset: (int, int)) -> P2duxResult<()> {
+        let _layer = display.push_layer(self.get_layer());
         let (base_x, base_y) = base;
         let (offset_x, offset_y) = offset;
         let sprites = self.get_sprites();
-        let sheets = &display.sheets;
         for st in sprites.iter() {
-            let sheet = sheets.get(&st.sheet).expect("DrawableItem.draw(): should be able to get sheet");
+            let sheet = try!(display.get_sheet(&st.sheet));
             let (tile_size_x, tile_size_y) = st.size;
             // should only need to get screen_x once this whole thing..
             // .. this implies getting rid of offset..
             let screen_x = base_x + (offset_x * tile_size_x as int) as int;
             let screen_y = base_y + (offset_y * tile_size_y as int) as int;
-            sheet.draw_tile(&*display.renderer, st,
-                            (screen_x, screen_y), st.size);
+            try!(display.draw_tile(&*sheet, st,
+                                   (screen_x, screen_y), st.size));
+        }
+        Ok(())
+    }
+
+    // Draws every sprite with its top-left at `world_pos`, as seen through
+    // `camera`, scaling with the camera's zoom. Sprites that would land
+    // entirely outside the camera's view are skipped.
+    fn draw_with_camera(&self, display: &GameDisplay, camera: &Camera,
+                        world_pos: (f64, f64)) -> P2duxResult<()> {
+        let _layer = display.push_layer(self.get_layer());
+        let (wx, wy) = world_pos;
+        let zoom = camera.get_zoom();
+        let screen_coords = camera.world_to_screen(world_pos);
+        for st in self.get_sprites().iter() {
+            let (w, h) = st.size;
+            if !camera.is_visible((wx, wy, w as f64, h as f64)) {
+                continue;
+            }
+            let sheet = try!(display.get_sheet(&st.sheet));
+            let dst_size = ((w as f64 * zoom).ceil() as uint,
+                            (h as f64 * zoom).ceil() as uint);
+            try!(display.draw_tile(&*sheet, st, screen_coords, dst_size));
         }
+        Ok(())
     }
 }
@@ -0,0 +1,311 @@
+// Copyright 2013-2014 Jeffery Olson
+//
+// Licensed under the 3-Clause BSD License, see LICENSE.txt
+// at the top-level of this repository.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::vec::Vec;
+use std::collections::HashMap;
+use std::io::{File, IoResult};
+
+use error::{P2duxError, P2duxResult};
+use super::snapshot::Frame;
+
+// Every frame is mapped onto a fixed 6x6x6 color cube, so this needs no
+// per-frame palette work; fine for bug report clips, not for trailers that
+// need smooth gradients.
+static CUBE_LEVELS: uint = 6;
+static MIN_CODE_SIZE: uint = 8;
+static MAX_CODE: uint = 4095;
+
+// Streams an endlessly looping animated GIF to a file, one frame at a time.
+// Frames that aren't the size the writer was opened with are skipped.
+pub struct GifWriter {
+    file: File,
+    path: String,
+    width: uint,
+    height: uint
+}
+
+impl GifWriter {
+    pub fn create(path: &Path, width: uint, height: uint) -> P2duxResult<GifWriter> {
+        let path_str = path.display().to_string();
+        let file = match File::create(path) {
+            Ok(f) => f,
+            Err(e) => return Err(P2duxError::Io(path_str, e.to_string()))
+        };
+        let mut writer = GifWriter { file: file, path: path_str, width: width, height: height };
+        let result = writer.write_header();
+        try!(writer.check(result));
+        Ok(writer)
+    }
+
+    // `delay_cs` is how long the frame stays up, in hundredths of a second
+    pub fn write_frame(&mut self, frame: &Frame, delay_cs: u16) -> P2duxResult<()> {
+        if frame.width != self.width || frame.height != self.height {
+            return Ok(());
+        }
+        let indices: Vec<u8> = frame.pixels.as_slice().chunks(4)
+            .map(|p| cube_index(p[0], p[1], p[2])).collect();
+        let data = lzw_encode(indices.as_slice());
+        let result = self.write_image(delay_cs, data.as_slice());
+        self.check(result)
+    }
+
+    pub fn finish(mut self) -> P2duxResult<()> {
+        let result = self.file.write_u8(0x3b);
+        self.check(result)
+    }
+
+    fn check(&self, result: IoResult<()>) -> P2duxResult<()> {
+        match result {
+            Ok(()) => Ok(()),
+            Err(e) => Err(P2duxError::Io(self.path.clone(), e.to_string()))
+        }
+    }
+
+    fn write_header(&mut self) -> IoResult<()> {
+        let f = &mut self.file;
+        try!(f.write(b"GIF89a"));
+        // logical screen descriptor: global table of 256 colors
+        try!(f.write_le_u16(self.width as u16));
+        try!(f.write_le_u16(self.height as u16));
+        try!(f.write(&[0xf7, 0, 0]));
+        for i in range(0u, 256) {
+            if i < CUBE_LEVELS * CUBE_LEVELS * CUBE_LEVELS {
+                let (r, g, b) = (i / 36, (i / 6) % 6, i % 6);
+                try!(f.write(&[(r * 51) as u8, (g * 51) as u8, (b * 51) as u8]));
+            } else {
+                try!(f.write(&[0, 0, 0]));
+            }
+        }
+        // loop forever
+        try!(f.write(&[0x21, 0xff, 0x0b]));
+        try!(f.write(b"NETSCAPE2.0"));
+        try!(f.write(&[0x03, 0x01, 0x00, 0x00, 0x00]));
+        Ok(())
+    }
+
+    fn write_image(&mut self, delay_cs: u16, data: &[u8]) -> IoResult<()> {
+        let f = &mut self.file;
+        // graphic control extension
+        try!(f.write(&[0x21, 0xf9, 0x04, 0x00]));
+        try!(f.write_le_u16(delay_cs));
+        try!(f.write(&[0x00, 0x00]));
+        // image descriptor, covering the whole screen
+        try!(f.write_u8(0x2c));
+        try!(f.write_le_u16(0));
+        try!(f.write_le_u16(0));
+        try!(f.write_le_u16(self.width as u16));
+        try!(f.write_le_u16(self.height as u16));
+        try!(f.write_u8(0x00));
+        try!(f.write_u8(MIN_CODE_SIZE as u8));
+        for block in data.chunks(255) {
+            try!(f.write_u8(block.len() as u8));
+            try!(f.write(block));
+        }
+        f.write_u8(0x00)
+    }
+}
+
+fn cube_index(r: u8, g: u8, b: u8) -> u8 {
+    let level = |c: u8| (c as uint * CUBE_LEVELS) / 256;
+    (level(r) * 36 + level(g) * 6 + level(b)) as u8
+}
+
+// packs variable width codes least significant bit first, as GIF wants
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u32,
+    bits: uint
+}
+
+impl BitWriter {
+    fn write(&mut self, code: uint, width: uint) {
+        self.acc |= (code as u32) << self.bits;
+        self.bits += width;
+        while self.bits >= 8 {
+            self.out.push((self.acc & 0xff) as u8);
+            self.acc >>= 8;
+            self.bits -= 8;
+        }
+    }
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.out.push((self.acc & 0xff) as u8);
+        }
+        self.out
+    }
+}
+
+fn lzw_encode(indices: &[u8]) -> Vec<u8> {
+    let clear = 1u << MIN_CODE_SIZE;
+    let end = clear + 1;
+    let mut dict: HashMap<(uint, u8), uint> = HashMap::new();
+    let mut next_code = end + 1;
+    let mut width = MIN_CODE_SIZE + 1;
+    let mut w = BitWriter { out: Vec::new(), acc: 0, bits: 0 };
+    w.write(clear, width);
+    let mut prefix: Option<uint> = None;
+    for k in indices.iter() {
+        let p = match prefix {
+            Some(p) => p,
+            None => { prefix = Some(*k as uint); continue; }
+        };
+        match dict.get(&(p, *k)) {
+            Some(code) => { prefix = Some(*code); continue; },
+            None => {}
+        }
+        w.write(p, width);
+        if next_code <= MAX_CODE {
+            dict.insert((p, *k), next_code);
+            next_code += 1;
+            if next_code > (1 << width) && width < 12 {
+                width += 1;
+            }
+        } else {
+            // table is full, start over
+            w.write(clear, width);
+            dict.clear();
+            next_code = end + 1;
+            width = MIN_CODE_SIZE + 1;
+        }
+        prefix = Some(*k as uint);
+    }
+    match prefix {
+        Some(p) => w.write(p, width),
+        None => {}
+    }
+    // the decoder adds one more entry on reading that last code, which can
+    // push it up to the next width before it reads the end code
+    if next_code == (1 << width) && width < 12 {
+        width += 1;
+    }
+    w.write(end, width);
+    w.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+    use super::{lzw_encode, MIN_CODE_SIZE, MAX_CODE};
+
+    struct Decoded {
+        indices: Vec<u8>,
+        clears: uint,
+        max_width: uint
+    }
+
+    // a straightforward GIF LZW decoder, to check the encoder against
+    fn lzw_decode(data: &[u8]) -> Decoded {
+        let clear = 1u << MIN_CODE_SIZE;
+        let end = clear + 1;
+        let reset = || -> Vec<Vec<u8>> {
+            let mut table: Vec<Vec<u8>> = range(0, clear).map(|i| vec![i as u8]).collect();
+            table.push(Vec::new());
+            table.push(Vec::new());
+            table
+        };
+        let mut table = reset();
+        let mut width = MIN_CODE_SIZE + 1;
+        let (mut pos, mut acc, mut bits) = (0u, 0u32, 0u);
+        let mut prev: Option<uint> = None;
+        let mut out = Decoded { indices: Vec::new(), clears: 0, max_width: width };
+        loop {
+            while bits < width {
+                acc |= (data[pos] as u32) << bits;
+                pos += 1;
+                bits += 8;
+            }
+            let code = (acc & ((1 << width) - 1)) as uint;
+            acc >>= width;
+            bits -= width;
+            if code == clear {
+                out.clears += 1;
+                table = reset();
+                width = MIN_CODE_SIZE + 1;
+                prev = None;
+                continue;
+            }
+            if code == end {
+                break;
+            }
+            let entry = if code < table.len() {
+                table[code].clone()
+            } else {
+                // the code being defined by this very step
+                let mut e = table[prev.unwrap()].clone();
+                let first = e[0];
+                e.push(first);
+                e
+            };
+            out.indices.push_all(entry.as_slice());
+            match prev {
+                Some(p) if table.len() <= MAX_CODE => {
+                    let mut e = table[p].clone();
+                    e.push(entry[0]);
+                    table.push(e);
+                    if table.len() == (1 << width) && width < 12 {
+                        width += 1;
+                        if width > out.max_width {
+                            out.max_width = width;
+                        }
+                    }
+                },
+                _ => {}
+            }
+            prev = Some(code);
+        }
+        out
+    }
+
+    // deterministic noise over the 216 palette indices
+    fn noise(len: uint) -> Vec<u8> {
+        let mut seed = 1u32;
+        range(0, len).map(|_| {
+            seed = seed * 1103515245 + 12345;
+            ((seed >> 16) % 216) as u8
+        }).collect()
+    }
+
+    #[test]
+    fn round_trip_empty() {
+        let decoded = lzw_decode(lzw_encode(&[]).as_slice());
+        assert!(decoded.indices.is_empty());
+    }
+
+    #[test]
+    fn round_trip_short() {
+        let indices = vec![1u8, 1, 1, 2, 1, 1, 2, 3, 0, 215];
+        let decoded = lzw_decode(lzw_encode(indices.as_slice()).as_slice());
+        assert_eq!(decoded.indices, indices);
+        assert_eq!(decoded.clears, 1);
+    }
+
+    #[test]
+    fn round_trip_through_width_changes() {
+        // enough to grow the codes past 9 bits, but not fill the table
+        let indices = noise(1000);
+        let decoded = lzw_decode(lzw_encode(indices.as_slice()).as_slice());
+        assert_eq!(decoded.indices, indices);
+        assert_eq!(decoded.clears, 1);
+        assert!(decoded.max_width > MIN_CODE_SIZE + 1);
+    }
+
+    #[test]
+    fn round_trip_through_table_reset() {
+        let indices = noise(30000);
+        let decoded = lzw_decode(lzw_encode(indices.as_slice()).as_slice());
+        assert_eq!(decoded.indices, indices);
+        assert_eq!(decoded.max_width, 12);
+        assert!(decoded.clears > 1);
+    }
+
+    #[test]
+    fn round_trip_long_run() {
+        let indices = Vec::from_elem(50000, 7u8);
+        let decoded = lzw_decode(lzw_encode(indices.as_slice()).as_slice());
+        assert_eq!(decoded.indices, indices);
+    }
+}
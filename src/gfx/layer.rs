@@ -0,0 +1,51 @@
+// Copyright 2013-2014 Jeffery Olson
+//
+// Licensed under the 3-Clause BSD License, see LICENSE.txt
+// at the top-level of this repository.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::GameDisplay;
+
+// Named render layers, lowest drawn first. They're spaced out so games can
+// slot their own numeric layers in between. Layer ordering only applies
+// while the display is batching; visibility and offsets always apply.
+pub const BACKGROUND: uint = 0;
+pub const WORLD: uint = 10;
+pub const ENTITIES: uint = 20;
+pub const UI: uint = 30;
+pub const OVERLAY: uint = 40;
+
+#[deriving(Clone, PartialEq, Show)]
+pub struct LayerSettings {
+    pub visible: bool,
+    // added to the destination of everything drawn on the layer
+    pub offset: (int, int)
+}
+
+impl LayerSettings {
+    pub fn new() -> LayerSettings {
+        LayerSettings { visible: true, offset: (0, 0) }
+    }
+}
+
+// Switches the display to a layer, and back to the previous one when it
+// goes out of scope.
+pub struct LayerGuard<'a> {
+    display: &'a GameDisplay,
+    prev: uint
+}
+
+impl<'a> LayerGuard<'a> {
+    pub fn new(display: &'a GameDisplay, layer: uint) -> LayerGuard<'a> {
+        let prev = display.set_layer(layer);
+        LayerGuard { display: display, prev: prev }
+    }
+}
+
+#[unsafe_destructor]
+impl<'a> Drop for LayerGuard<'a> {
+    fn drop(&mut self) {
+        self.display.set_layer(self.prev);
+    }
+}
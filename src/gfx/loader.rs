@@ -0,0 +1,122 @@
+// Copyright 2013-2014 Jeffery Olson
+//
+// Licensed under the 3-Clause BSD License, see LICENSE.txt
+// at the top-level of this repository.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::vec::Vec;
+use std::mem;
+use std::comm::{channel, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUint, Ordering};
+
+use p2d::sprite::SpriteSheet;
+
+use error::{P2duxError, P2duxResult};
+use super::GameDisplay;
+use super::snapshot::Frame;
+use super::texture::{TextureSheet, SheetHandle};
+use super::vfs::SharedVfs;
+
+// name, path, whether it's on disk and the decoded image (or why it
+// couldn't be decoded)
+type Decoded = (String, String, bool, P2duxResult<Frame>);
+
+// Loads sheets without blocking a frame: pngs are decoded on a worker task
+// as soon as the loader starts, while turning them into textures (which
+// has to happen on the main thread) is done a few at a time by calling
+// upload_ready() once a frame. Paths are resolved through `vfs` when one
+// is given, as GameDisplay::load_sheet() does. A sheet that fails to decode
+// or upload is set aside in get_failed() and the rest carry on loading.
+pub struct SheetLoader {
+    total: uint,
+    decoded: Arc<AtomicUint>,
+    uploaded: uint,
+    failed: Vec<(String, P2duxError)>,
+    rx: Receiver<Decoded>,
+    handles: Vec<SheetHandle>
+}
+
+impl SheetLoader {
+    // pass display.get_vfs() to load the same way the display does
+    pub fn start(sheets: Vec<SpriteSheet>, vfs: Option<SharedVfs>) -> SheetLoader {
+        let (tx, rx) = channel();
+        let decoded = Arc::new(AtomicUint::new(0));
+        let worker_decoded = decoded.clone();
+        let total = sheets.len();
+        spawn(proc() {
+            for s in sheets.iter() {
+                let frame = match vfs {
+                    Some(ref vfs) => vfs.read(s.path.as_slice()).and_then(|bytes| {
+                        Frame::load_png_bytes(bytes.as_slice(), s.path.as_slice())
+                    }),
+                    None => Frame::load_png(&Path::new(s.path.as_slice()))
+                };
+                worker_decoded.fetch_add(1, Ordering::SeqCst);
+                // the loader was dropped, so nobody's left to upload the rest
+                if tx.send_opt((s.name.clone(), s.path.clone(), vfs.is_none(), frame)).is_err() {
+                    break;
+                }
+            }
+        });
+        SheetLoader { total: total, decoded: decoded, uploaded: 0, failed: Vec::new(),
+                      rx: rx, handles: Vec::new() }
+    }
+
+    pub fn get_total(&self) -> uint { self.total }
+    pub fn get_decoded(&self) -> uint { self.decoded.load(Ordering::SeqCst) }
+    pub fn get_uploaded(&self) -> uint { self.uploaded }
+    // names of the sheets that couldn't be loaded, and why
+    pub fn get_failed<'a>(&'a self) -> &'a [(String, P2duxError)] { self.failed.as_slice() }
+    // every sheet has either been uploaded or failed
+    pub fn is_done(&self) -> bool { self.uploaded + self.failed.len() == self.total }
+
+    // 0.0 to 1.0, with decoding and uploading each counting for half
+    pub fn get_progress(&self) -> f64 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        let finished = self.uploaded + self.failed.len();
+        (self.get_decoded() + finished) as f64 / (self.total * 2) as f64
+    }
+
+    // Uploads up to `max` sheets that have finished decoding into `display`,
+    // returning how many were uploaded. Sheets that fail count towards `max`
+    // and are added to get_failed() rather than returned as errors.
+    pub fn upload_ready(&mut self, display: &GameDisplay, max: uint) -> P2duxResult<uint> {
+        let (mut attempted, mut uploaded) = (0, 0);
+        while attempted < max && !self.is_done() {
+            let (name, path, on_disk, frame) = match self.rx.try_recv() {
+                Ok(decoded) => decoded,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Err(P2duxError::LoaderDisconnected)
+            };
+            attempted += 1;
+            match upload(display, name.clone(), &path, on_disk, frame) {
+                Ok(handle) => {
+                    self.handles.push(handle);
+                    self.uploaded += 1;
+                    uploaded += 1;
+                },
+                Err(e) => self.failed.push((name, e))
+            }
+        }
+        Ok(uploaded)
+    }
+
+    // hands over the handles for everything uploaded so far
+    pub fn take_handles(&mut self) -> Vec<SheetHandle> {
+        let mut handles = Vec::new();
+        mem::swap(&mut handles, &mut self.handles);
+        handles
+    }
+}
+
+fn upload(display: &GameDisplay, name: String, path: &String, on_disk: bool,
+          frame: P2duxResult<Frame>) -> P2duxResult<SheetHandle> {
+    let surface = try!(try!(frame).to_surface());
+    let sheet = try!(TextureSheet::from_surface(&*display.renderer, surface, path,
+                                                name, on_disk));
+    Ok(display.add_sheet(sheet))
+}
@@ -0,0 +1,143 @@
+// Copyright 2013-2014 Jeffery Olson
+//
+// Licensed under the 3-Clause BSD License, see LICENSE.txt
+// at the top-level of this repository.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::cmp::{max, min};
+
+use sdl2::hint;
+use sdl2::rect::Rect;
+use sdl2::pixels::{Color, PixelFormatFlag};
+use sdl2::render::{Renderer, Texture, TextureAccess};
+
+use error::{P2duxError, P2duxResult};
+use super::GameDisplay;
+
+// Offscreen texture everything is drawn into when the display has a logical
+// size; present() scales it onto the window.
+pub struct LogicalTarget {
+    pub size: (uint, uint),
+    pub texture: Texture
+}
+
+// Where a logical image of `logical` size lands in an `output` sized window:
+// scaled by the largest whole factor that fits (never less than 1) and
+// centered, leaving letterbox/pillarbox bars around it.
+pub fn scaled_rect(output: (int, int), logical: (uint, uint)) -> Rect {
+    let (ow, oh) = output;
+    let (lw, lh) = logical;
+    let (lw, lh) = (max(lw, 1) as int, max(lh, 1) as int);
+    let scale = max(1, min(ow / lw, oh / lh));
+    let (w, h) = (lw * scale, lh * scale);
+    Rect::new(((ow - w) / 2) as i32, ((oh - h) / 2) as i32, w as i32, h as i32)
+}
+
+// Fills the window with the bar color, copies the logical image into the
+// scaled rect, presents, then points rendering back at the logical image.
+pub fn present_scaled(renderer: &Renderer, target: &LogicalTarget,
+                      bar_color: Color) -> P2duxResult<()> {
+    let output = match renderer.get_output_size() {
+        Ok(size) => size,
+        Err(e) => return Err(P2duxError::RenderCall(format!("present(): get_output_size(): {}", e)))
+    };
+    try!(set_target(renderer, None));
+    match renderer.set_draw_color(bar_color) {
+        Ok(()) => {},
+        Err(e) => return Err(P2duxError::RenderCall(format!("present(): set_draw_color(): {}", e)))
+    }
+    match renderer.clear() {
+        Ok(()) => {},
+        Err(e) => return Err(P2duxError::RenderCall(format!("present(): clear(): {}", e)))
+    }
+    let dst = scaled_rect(output, target.size);
+    match renderer.copy(&target.texture, None, Some(dst)) {
+        Ok(()) => {},
+        Err(e) => return Err(P2duxError::RenderCall(format!("present(): copy of logical image: {}", e)))
+    }
+    renderer.present();
+    set_target(renderer, Some(&target.texture))
+}
+
+fn set_target(renderer: &Renderer, texture: Option<&Texture>) -> P2duxResult<()> {
+    match renderer.set_render_target(texture) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(P2duxError::RenderCall(format!("set_render_target(): {}", e)))
+    }
+}
+
+impl GameDisplay {
+    // Draw at a fixed logical resolution instead of in window pixels. The
+    // logical image is scaled up by whole multiples only, so pixel art stays
+    // crisp, with the leftover window space filled with the letterbox color.
+    // None goes back to drawing straight to the window.
+    pub fn set_logical_size(&self, size: Option<(uint, uint)>) -> P2duxResult<()> {
+        try!(self.flush());
+        let target = match size {
+            Some((w, h)) => {
+                // nearest-neighbour scaling for the logical texture
+                hint::set("SDL_RENDER_SCALE_QUALITY", "0");
+                let texture = self.renderer.create_texture(
+                    PixelFormatFlag::ARGB8888, TextureAccess::Target, w as int, h as int);
+                let texture = match texture {
+                    Ok(t) => t,
+                    Err(e) => return Err(P2duxError::RenderCall(
+                        format!("set_logical_size(): couldn't create target texture: {}", e)))
+                };
+                try!(set_target(&*self.renderer, Some(&texture)));
+                Some(LogicalTarget { size: (w, h), texture: texture })
+            },
+            None => {
+                try!(set_target(&*self.renderer, None));
+                None
+            }
+        };
+        *self.logical.borrow_mut() = target;
+        Ok(())
+    }
+
+    pub fn get_logical_size(&self) -> Option<(uint, uint)> {
+        match *self.logical.borrow() {
+            Some(ref target) => Some(target.size),
+            None => None
+        }
+    }
+
+    // kept across set_logical_size() calls, so it can be set before or after
+    pub fn set_letterbox_color(&self, rgb: (u8, u8, u8)) {
+        let (r, g, b) = rgb;
+        *self.letterbox_color.borrow_mut() = Color::RGB(r, g, b);
+    }
+    pub fn get_letterbox_color(&self) -> Color { self.letterbox_color.borrow().clone() }
+
+    // the window rect the logical image is scaled into, if there is one
+    pub fn get_scaled_rect(&self) -> Option<Rect> {
+        let size = match self.get_logical_size() {
+            Some(size) => size,
+            None => return None
+        };
+        match self.renderer.get_output_size() {
+            Ok(output) => Some(scaled_rect(output, size)),
+            Err(_) => None
+        }
+    }
+
+    // Converts window coordinates (e.g. from mouse events) to logical
+    // coordinates. None when the point is on the letterbox bars. Without a
+    // logical size the coordinates are passed straight through.
+    pub fn window_to_logical(&self, coords: (int, int)) -> Option<(int, int)> {
+        let (size, rect) = match (self.get_logical_size(), self.get_scaled_rect()) {
+            (Some(size), Some(rect)) => (size, rect),
+            _ => return Some(coords)
+        };
+        let (x, y) = coords;
+        let (rx, ry) = (x - rect.x as int, y - rect.y as int);
+        if rx < 0 || ry < 0 || rx >= rect.w as int || ry >= rect.h as int {
+            return None;
+        }
+        let (lw, _) = size;
+        let scale = rect.w as int / max(lw, 1) as int;
+        Some((rx / scale, ry / scale))
+    }
+}
@@ -6,80 +6,790 @@
 // except according to those terms.
 
 use std::vec::Vec;
-use std::collections::HashMap;
+use std::os;
+use std::cell::{Cell, RefCell};
+use std::rc;
+use std::rc::Rc;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, StaticMutex, MUTEX_INIT};
+use std::sync::atomic::{AtomicUint, INIT_ATOMIC_UINT, Ordering};
+use time::precise_time_ns;
 use sdl2::sdl;
 use sdl2::render::Renderer;
-use sdl2::video::{WindowPos, Window, FullscreenType};
-use p2d::sprite::SpriteSheet;
+use sdl2::surface::Surface;
+use sdl2::video::WindowPos;
+use p2d::sprite::{SpriteSheet, SpriteTile};
 use sdl2;
 use sdl2::pixels::Color;
+use sdl2::rect::Rect;
 use sdl2_image;
+use sdl2_image::LoadSurface;
 
+use error::{P2duxError, P2duxResult};
+use self::config::{DisplayConfig, WindowPosition, RendererBackend};
+use self::window::WindowMode;
+
+pub mod batch;
+pub mod camera;
+pub mod config;
 pub mod draw;
+pub mod gif;
+pub mod layer;
+pub mod loader;
+pub mod logical;
+pub mod record;
 pub mod texture;
+pub mod viewport;
+pub mod window;
+pub mod snapshot;
+pub mod stats;
+pub mod target;
+pub mod vfs;
 
 pub struct GameDisplay {
     pub renderer: Box<Renderer>,
-    pub sheets: texture::TextureSheets
+    sheets: RefCell<texture::TextureSheets>,
+    // sheets loaded with load_sheet(), which release_unused_sheets() may unload
+    handled_sheets: RefCell<HashSet<String>>,
+    // sheets given to the constructor, which are never released
+    pinned_sheets: RefCell<HashSet<String>>,
+    stats: RefCell<stats::RenderStats>,
+    last_stats: RefCell<stats::RenderStats>,
+    last_sheet: RefCell<Option<String>>,
+    batching: Cell<bool>,
+    queue: RefCell<batch::DrawQueue>,
+    current_layer: Cell<uint>,
+    layers: RefCell<HashMap<uint, layer::LayerSettings>>,
+    draw_color: RefCell<Color>,
+    logical: RefCell<Option<logical::LogicalTarget>>,
+    letterbox_color: RefCell<Color>,
+    pending_resize: Cell<Option<(int, int)>>,
+    recorder: RefCell<Option<record::FrameRecorder>>,
+    recording_error: RefCell<Option<P2duxError>>,
+    // size of the RenderTarget bound with begin_target(), if any
+    target_size: Cell<Option<(uint, uint)>>,
+    hot_reload: Cell<bool>,
+    next_reload_check: Cell<u64>,
+    vfs: RefCell<Option<vfs::SharedVfs>>,
+    keep_surfaces: Cell<bool>,
+    // bytes of sheet memory present() evicts down to, if any
+    texture_budget: Cell<Option<uint>>,
+    // counts presented frames, for least-recently-drawn eviction
+    frame_number: Cell<u64>,
+    // keep last, so SDL outlives everything above
+    _sdl: SdlContext
+}
+
+// SDL and sdl2_image are process wide, so they're started by the first live
+// GameDisplay and only shut down once the last one is dropped; displays on
+// other threads (say, parallel tests) don't pull SDL out from under each
+// other. Construction and teardown are serialized through SDL_LOCK, but the
+// displays themselves still share SDL's global state.
+static SDL_LOCK: StaticMutex = MUTEX_INIT;
+static LIVE_DISPLAYS: AtomicUint = INIT_ATOMIC_UINT;
+
+struct SdlContext;
+
+impl SdlContext {
+    // `video_driver` is only honoured when this starts SDL, and is set just
+    // for the duration of the init
+    fn acquire(video_driver: Option<&str>) -> SdlContext {
+        let _guard = SDL_LOCK.lock();
+        if LIVE_DISPLAYS.fetch_add(1, Ordering::SeqCst) == 0 {
+            let prev_driver = os::getenv("SDL_VIDEODRIVER");
+            match video_driver {
+                Some(driver) => os::setenv("SDL_VIDEODRIVER", driver),
+                None => {}
+            }
+            sdl::init(sdl2::INIT_VIDEO);
+            sdl2_image::init(sdl2_image::INIT_PNG);
+            if video_driver.is_some() {
+                match prev_driver {
+                    Some(driver) => os::setenv("SDL_VIDEODRIVER", driver),
+                    None => os::unsetenv("SDL_VIDEODRIVER")
+                }
+            }
+        }
+        SdlContext
+    }
+}
+
+impl Drop for SdlContext {
+    fn drop(&mut self) {
+        let _guard = SDL_LOCK.lock();
+        if LIVE_DISPLAYS.fetch_sub(1, Ordering::SeqCst) == 1 {
+            sdl2_image::quit();
+            sdl::quit();
+        }
+    }
 }
 
 impl GameDisplay {
-    pub fn new(title: &str, screen_size: (int, int, bool), ss: Vec<SpriteSheet>) -> GameDisplay {
+    pub fn new(title: &str, screen_size: (int, int, bool), ss: Vec<SpriteSheet>) -> P2duxResult<GameDisplay> {
+        let (width, height, fullscreen) = screen_size;
+        let mode = if fullscreen { WindowMode::Fullscreen } else { WindowMode::Windowed };
+        let config = DisplayConfig::new(title, (width, height)).window_mode(mode);
+        GameDisplay::from_config(&config, ss)
+    }
+
+    pub fn from_config(config: &DisplayConfig, ss: Vec<SpriteSheet>) -> P2duxResult<GameDisplay> {
+        GameDisplay::from_config_with_vfs(config, ss, None)
+    }
+
+    // As from_config(), with the sheets in `ss` (and later load_sheet()
+    // calls) read through `vfs`, see set_vfs()
+    pub fn from_config_with_vfs(config: &DisplayConfig, ss: Vec<SpriteSheet>,
+                                vfs: Option<Box<vfs::Vfs + Send + Sync + 'static>>)
+            -> P2duxResult<GameDisplay> {
         // first thing we do
-        sdl::init(sdl2::INIT_VIDEO);
-        // and sdl2_image
-        sdl2_image::init(sdl2_image::INIT_PNG);
+        let sdl = SdlContext::acquire(None);
 
-        let (width, height, fullscreen) = screen_size;
+        let (width, height) = config.size;
+        let (x, y) = match config.position {
+            WindowPosition::Centered => (WindowPos::PosCentered, WindowPos::PosCentered),
+            WindowPosition::Undefined => (WindowPos::PosUndefined, WindowPos::PosUndefined),
+            WindowPosition::At(x, y) => (WindowPos::Positioned(x), WindowPos::Positioned(y))
+        };
+        let mut window_flags = match config.backend {
+            RendererBackend::Accelerated => sdl2::video::OPENGL,
+            RendererBackend::Software => sdl2::video::WindowFlags::empty()
+        };
+        if config.resizable {
+            window_flags = window_flags | sdl2::video::RESIZABLE;
+        }
         let window = sdl2::video::Window::new(
-            title, WindowPos::PosCentered, WindowPos::PosCentered,
-            width, height, sdl2::video::OPENGL);
+            config.title.as_slice(), x, y, width, height, window_flags);
         let window = match window {
             Ok(window) => window,
-            Err(err) => panic!(format!("failed to create window: {}", err))
+            Err(err) => return Err(P2duxError::WindowCreation(err))
+        };
+        if config.window_mode != WindowMode::Windowed {
+            window.set_fullscreen(window::fullscreen_type(config.window_mode));
+        }
+        match config.min_size {
+            Some((w, h)) => window.set_minimum_size(w, h),
+            None => {}
+        }
+        match config.icon_path {
+            Some(ref path) => {
+                let icon: Surface = match LoadSurface::from_file(&Path::new(path.as_slice())) {
+                    Ok(s) => s,
+                    Err(msg) => return Err(P2duxError::ImageLoad(path.clone(), msg))
+                };
+                window.set_icon(&icon);
+            },
+            None => {}
+        }
+
+        let mut renderer_flags = match config.backend {
+            RendererBackend::Accelerated => sdl2::render::ACCELERATED,
+            RendererBackend::Software => sdl2::render::SOFTWARE
         };
-        if fullscreen {
-            window.set_fullscreen(FullscreenType::FTTrue);
+        if config.vsync {
+            renderer_flags = renderer_flags | sdl2::render::PRESENTVSYNC;
         }
         let renderer = Renderer::from_window(
-            window, sdl2::render::RenderDriverIndex::Auto, sdl2::render::ACCELERATED);
+            window, sdl2::render::RenderDriverIndex::Auto, renderer_flags);
         let renderer = match renderer {
             Ok(renderer) => renderer,
-            Err(err) => panic!(format!("failed to create renderer: {}", err))
+            Err(err) => return Err(P2duxError::RendererCreation(err))
         };
-        let mut display = GameDisplay {
+        let display = try!(GameDisplay::from_renderer(renderer, ss, vfs, sdl));
+        match config.letterbox_color {
+            Some(rgb) => display.set_letterbox_color(rgb),
+            None => {}
+        }
+        if config.logical_size.is_some() {
+            try!(display.set_logical_size(config.logical_size));
+        }
+        Ok(display)
+    }
+
+    // Headless construction: no window is ever shown. SDL is pointed at its
+    // dummy video driver (if it isn't already running) and everything is
+    // drawn by the software renderer into an offscreen RGBA surface of the
+    // given size, so the same sheets and drawing API can be used on machines
+    // without a GPU or display.
+    pub fn new_headless(screen_size: (int, int), ss: Vec<SpriteSheet>) -> P2duxResult<GameDisplay> {
+        GameDisplay::new_headless_with_vfs(screen_size, ss, None)
+    }
+
+    pub fn new_headless_with_vfs(screen_size: (int, int), ss: Vec<SpriteSheet>,
+                                 vfs: Option<Box<vfs::Vfs + Send + Sync + 'static>>)
+            -> P2duxResult<GameDisplay> {
+        let sdl = SdlContext::acquire(Some("dummy"));
+
+        let (width, height) = screen_size;
+        let surface = Surface::new(
+            sdl2::surface::SWSURFACE, width, height, 32,
+            0x000000ff, 0x0000ff00, 0x00ff0000, 0xff000000);
+        let surface = match surface {
+            Ok(surface) => surface,
+            Err(err) => return Err(P2duxError::RendererCreation(
+                format!("failed to create headless surface: {}", err)))
+        };
+        let renderer = match Renderer::from_surface(surface) {
+            Ok(renderer) => renderer,
+            Err(err) => return Err(P2duxError::RendererCreation(err))
+        };
+        GameDisplay::from_renderer(renderer, ss, vfs, sdl)
+    }
+
+    fn from_renderer(renderer: Renderer, ss: Vec<SpriteSheet>,
+                     vfs: Option<Box<vfs::Vfs + Send + Sync + 'static>>,
+                     sdl: SdlContext) -> P2duxResult<GameDisplay> {
+        let display = GameDisplay {
             renderer: box renderer,
-            sheets: HashMap::new()
+            sheets: RefCell::new(HashMap::new()),
+            handled_sheets: RefCell::new(HashSet::new()),
+            pinned_sheets: RefCell::new(HashSet::new()),
+            stats: RefCell::new(stats::RenderStats::new()),
+            last_stats: RefCell::new(stats::RenderStats::new()),
+            last_sheet: RefCell::new(None),
+            batching: Cell::new(false),
+            queue: RefCell::new(batch::DrawQueue::new()),
+            current_layer: Cell::new(layer::BACKGROUND),
+            layers: RefCell::new(HashMap::new()),
+            draw_color: RefCell::new(Color::RGB(0, 0, 0)),
+            logical: RefCell::new(None),
+            letterbox_color: RefCell::new(Color::RGB(0, 0, 0)),
+            pending_resize: Cell::new(None),
+            recorder: RefCell::new(None),
+            recording_error: RefCell::new(None),
+            target_size: Cell::new(None),
+            hot_reload: Cell::new(false),
+            next_reload_check: Cell::new(0),
+            vfs: RefCell::new(vfs.map(|v| Arc::new(v))),
+            keep_surfaces: Cell::new(true),
+            texture_budget: Cell::new(None),
+            frame_number: Cell::new(0),
+            _sdl: sdl
         };
         // build TextureSheets
         for s in ss.iter() {
-            display.sheets.insert(s.name.clone(), texture::TextureSheet::new(
-                &*display.renderer,
-                &s.path,
-                s.name.clone()));
+            let sheet = try!(display.read_sheet(s));
+            // counts as drawn now, so it isn't the first thing evicted
+            sheet.mark_drawn(display.frame_number.get());
+            display.sheets.borrow_mut().insert(s.name.clone(), Rc::new(sheet));
+            display.pinned_sheets.borrow_mut().insert(s.name.clone());
         }
-        display
+        Ok(display)
     }
 
-    pub fn set_draw_color(&self, rgb: (u8, u8, u8)) {
-        let (r, g, b) = rgb;
-        match self.renderer.set_draw_color(Color::RGB(r, g, b)) {
+    pub fn get_sheet(&self, name: &String) -> P2duxResult<Rc<texture::TextureSheet>> {
+        match self.sheets.borrow().get(name) {
+            Some(sheet) => Ok(sheet.clone()),
+            None => Err(P2duxError::MissingSheet(name.clone()))
+        }
+    }
+
+    pub fn has_sheet(&self, name: &String) -> bool {
+        self.sheets.borrow().contains_key(name)
+    }
+
+    pub fn get_sheet_count(&self) -> uint { self.sheets.borrow().len() }
+
+    pub fn get_sheet_names(&self) -> Vec<String> {
+        self.sheets.borrow().keys().map(|k| k.clone()).collect()
+    }
+
+    // Loads a sheet at runtime (or finds it, if a sheet by that name is
+    // already loaded), handing back a handle that keeps it in use
+    pub fn load_sheet(&self, ss: &SpriteSheet) -> P2duxResult<texture::SheetHandle> {
+        let existing = self.sheets.borrow().get(&ss.name).map(|s| s.clone());
+        match existing {
+            Some(sheet) => {
+                if !self.pinned_sheets.borrow().contains(&ss.name) {
+                    self.handled_sheets.borrow_mut().insert(ss.name.clone());
+                }
+                Ok(texture::SheetHandle::new(sheet))
+            },
+            None => {
+                let sheet = try!(self.read_sheet(ss));
+                Ok(self.add_sheet(sheet))
+            }
+        }
+    }
+
+    // builds a sheet from the vfs, if there is one, or from disk
+    fn read_sheet(&self, ss: &SpriteSheet) -> P2duxResult<texture::TextureSheet> {
+        match *self.vfs.borrow() {
+            Some(ref vfs) => {
+                let bytes = try!(vfs.read(ss.path.as_slice()));
+                texture::TextureSheet::from_bytes(
+                    &*self.renderer, bytes.as_slice(), &ss.path, ss.name.clone())
+            },
+            None => texture::TextureSheet::new(&*self.renderer, &ss.path, ss.name.clone())
+        }
+    }
+
+    // Loads a sheet straight from png bytes, e.g. from include_bin!(),
+    // replacing any sheet of the same name
+    pub fn load_sheet_bytes(&self, name: &String, bytes: &[u8]) -> P2duxResult<texture::SheetHandle> {
+        let source = format!("<bytes for '{}'>", name);
+        let sheet = try!(texture::TextureSheet::from_bytes(
+            &*self.renderer, bytes, &source, name.clone()));
+        Ok(self.add_sheet(sheet))
+    }
+
+    // While set, load_sheet() resolves SpriteSheet paths through `vfs`
+    // instead of the filesystem. To have the constructor's sheets read
+    // through it too, use from_config_with_vfs() or new_headless_with_vfs().
+    pub fn set_vfs(&self, vfs: Option<Box<vfs::Vfs + Send + Sync + 'static>>) {
+        *self.vfs.borrow_mut() = vfs.map(|v| Arc::new(v));
+    }
+
+    pub fn has_vfs(&self) -> bool { self.vfs.borrow().is_some() }
+
+    // for handing to SheetLoader::start()
+    pub fn get_vfs(&self) -> Option<vfs::SharedVfs> { self.vfs.borrow().clone() }
+
+    // Adds an already built sheet as if by load_sheet(), replacing any sheet
+    // of the same name
+    pub fn add_sheet(&self, sheet: texture::TextureSheet) -> texture::SheetHandle {
+        let name = sheet.get_name().clone();
+        if !self.keep_surfaces.get() {
+            sheet.drop_surface();
+        }
+        // a new sheet counts as drawn now, so it isn't the first thing
+        // enforce_texture_budget() evicts
+        sheet.mark_drawn(self.frame_number.get());
+        let sheet = Rc::new(sheet);
+        self.sheets.borrow_mut().insert(name.clone(), sheet.clone());
+        self.pinned_sheets.borrow_mut().remove(&name);
+        self.handled_sheets.borrow_mut().insert(name);
+        texture::SheetHandle::new(sheet)
+    }
+
+    // Removes a sheet right away, handles or not; returns whether it was
+    // loaded. Outstanding handles keep the texture alive, but drawing by
+    // name fails with MissingSheet from here on.
+    pub fn unload_sheet(&self, name: &String) -> bool {
+        self.handled_sheets.borrow_mut().remove(name);
+        self.pinned_sheets.borrow_mut().remove(name);
+        self.sheets.borrow_mut().remove(name).is_some()
+    }
+
+    // Unloads every sheet from load_sheet() that no longer has any handles,
+    // returning their names. Sheets given to the constructor stay loaded.
+    pub fn release_unused_sheets(&self) -> Vec<String> {
+        let unused: Vec<String> = {
+            let sheets = self.sheets.borrow();
+            self.handled_sheets.borrow().iter().filter(|name| {
+                match sheets.get(*name) {
+                    // only the map itself still refers to it
+                    Some(sheet) => rc::strong_count(sheet) == 1,
+                    None => true
+                }
+            }).map(|name| name.clone()).collect()
+        };
+        for name in unused.iter() {
+            self.unload_sheet(name);
+        }
+        unused
+    }
+
+    // While batching, draw_tile() and fill_rect() queue their work instead of
+    // hitting the renderer; the queue is sorted and drawn by flush(), which
+    // present() and read_frame() call. Turning batching off flushes.
+    pub fn set_batching(&self, enabled: bool) -> P2duxResult<()> {
+        self.batching.set(enabled);
+        if !enabled {
+            try!(self.flush());
+        }
+        Ok(())
+    }
+    pub fn is_batching(&self) -> bool { self.batching.get() }
+
+    // Layer that subsequent draws go into. Returns the previous layer so
+    // callers can restore it. Lower layers are only drawn first while
+    // batching, i.e. after set_batching(true); batching is off by default,
+    // and then draws land in the order they're made, whatever their layer.
+    // A layer's visibility and offset apply either way.
+    pub fn set_layer(&self, layer: uint) -> uint {
+        let prev = self.current_layer.get();
+        self.current_layer.set(layer);
+        prev
+    }
+    // the layer draws currently go into; see set_layer() for when that
+    // affects draw order
+    pub fn get_layer(&self) -> uint { self.current_layer.get() }
+
+    // set_layer() until the returned guard is dropped; ordering by layer
+    // needs set_batching(true), as for set_layer()
+    pub fn push_layer<'a>(&'a self, layer: uint) -> layer::LayerGuard<'a> {
+        layer::LayerGuard::new(self, layer)
+    }
+
+    pub fn get_layer_settings(&self, layer: uint) -> layer::LayerSettings {
+        match self.layers.borrow().get(&layer) {
+            Some(settings) => settings.clone(),
+            None => layer::LayerSettings::new()
+        }
+    }
+    pub fn set_layer_settings(&self, layer: uint, settings: layer::LayerSettings) {
+        self.layers.borrow_mut().insert(layer, settings);
+    }
+    pub fn set_layer_visible(&self, layer: uint, visible: bool) {
+        let mut settings = self.get_layer_settings(layer);
+        settings.visible = visible;
+        self.set_layer_settings(layer, settings);
+    }
+    pub fn set_layer_offset(&self, layer: uint, offset: (int, int)) {
+        let mut settings = self.get_layer_settings(layer);
+        settings.offset = offset;
+        self.set_layer_settings(layer, settings);
+    }
+
+    // the current layer's offset applied to `dst`, or None if it's hidden
+    fn place_on_layer(&self, dst: Rect) -> Option<Rect> {
+        let settings = self.get_layer_settings(self.current_layer.get());
+        if !settings.visible {
+            return None;
+        }
+        let (ox, oy) = settings.offset;
+        Some(Rect::new(dst.x + ox as i32, dst.y + oy as i32, dst.w, dst.h))
+    }
+
+    pub fn draw_tile(&self, sheet: &texture::TextureSheet, st: &SpriteTile,
+                     dst_coords: (int, int), dst_size: (uint, uint)) -> P2duxResult<()> {
+        let (src, dst) = texture::tile_rects(st, dst_coords, dst_size);
+        let dst = match self.place_on_layer(dst) {
+            Some(dst) => dst,
+            None => return Ok(())
+        };
+        if self.batching.get() {
+            self.queue.borrow_mut().push(
+                self.current_layer.get(),
+                batch::DrawCommand::Tile(sheet.get_name().clone(), src, dst));
+            return Ok(());
+        }
+        self.copy_tile(sheet, src, dst)
+    }
+
+    fn copy_tile(&self, sheet: &texture::TextureSheet, src: Rect, dst: Rect) -> P2duxResult<()> {
+        try!(self.restore_sheet(sheet));
+        sheet.mark_drawn(self.frame_number.get());
+        let result = sheet.copy(&*self.renderer, src, dst);
+        let mut stats = self.stats.borrow_mut();
+        let mut last_sheet = self.last_sheet.borrow_mut();
+        if result.is_ok() {
+            stats.record_tile_copy(sheet.get_name());
+            let switched = match *last_sheet {
+                Some(ref name) => name != sheet.get_name(),
+                None => true
+            };
+            if switched {
+                stats.texture_switches += 1;
+                *last_sheet = Some(sheet.get_name().clone());
+            }
+        } else {
+            stats.failed_copies += 1;
+        }
+        result
+    }
+
+    // fills with the current draw color
+    pub fn fill_rect(&self, rect: &Rect) -> P2duxResult<()> {
+        let rect = match self.place_on_layer(*rect) {
+            Some(rect) => rect,
+            None => return Ok(())
+        };
+        if self.batching.get() {
+            let color = self.draw_color.borrow().clone();
+            self.queue.borrow_mut().push(
+                self.current_layer.get(), batch::DrawCommand::Fill(color, rect));
+            return Ok(());
+        }
+        self.stats.borrow_mut().fill_rects += 1;
+        match self.renderer.fill_rect(&rect) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(P2duxError::RenderCall(format!("fill_rect(): {}", e)))
+        }
+    }
+
+    // draws everything queued while batching, in layer/sheet order
+    pub fn flush(&self) -> P2duxResult<()> {
+        let items = self.queue.borrow_mut().take_sorted();
+        if items.is_empty() {
+            return Ok(());
+        }
+        let color = self.draw_color.borrow().clone();
+        let drawn = self.draw_queued(items);
+        // leave the draw color as the caller last set it, even if a draw
+        // failed part way through
+        let restored = self.set_draw_sdl2_color(color);
+        try!(drawn);
+        restored
+    }
+
+    fn draw_queued(&self, items: Vec<batch::QueuedDraw>) -> P2duxResult<()> {
+        for item in items.into_iter() {
+            match item.cmd {
+                batch::DrawCommand::Tile(name, src, dst) => {
+                    let sheet = try!(self.get_sheet(&name));
+                    try!(self.copy_tile(&*sheet, src, dst));
+                },
+                batch::DrawCommand::Fill(c, rect) => {
+                    try!(self.set_draw_sdl2_color(c));
+                    self.stats.borrow_mut().fill_rects += 1;
+                    match self.renderer.fill_rect(&rect) {
+                        Ok(()) => {},
+                        Err(e) => return Err(P2duxError::RenderCall(format!("flush(): fill_rect(): {}", e)))
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // stats accumulated so far in the frame currently being drawn
+    pub fn get_stats(&self) -> stats::RenderStats { self.stats.borrow().clone() }
+    // stats for the most recently presented frame
+    pub fn get_last_stats(&self) -> stats::RenderStats { self.last_stats.borrow().clone() }
+
+    pub fn reset_stats(&self) {
+        *self.stats.borrow_mut() = stats::RenderStats::new();
+        *self.last_sheet.borrow_mut() = None;
+    }
+
+    // Flushes any batched draws, shows the frame and starts accounting for
+    // the next one
+    pub fn present(&self) -> P2duxResult<()> {
+        if self.is_on_target() {
+            try!(self.end_target());
+        }
+        try!(self.flush());
+        match self.record_frame() {
             Ok(()) => {},
-            Err(e) => panic!("set_draw_color(): failure: {}", e)
+            Err(e) => {
+                // a clip that can't be written shouldn't take the game down
+                // with it; the recording is dropped and the frame still shown
+                let _ = self.stop_recording();
+                *self.recording_error.borrow_mut() = Some(e);
+            }
+        }
+        match *self.logical.borrow() {
+            Some(ref target) => {
+                try!(logical::present_scaled(&*self.renderer, target, self.get_letterbox_color()));
+                let color = self.draw_color.borrow().clone();
+                try!(self.set_draw_sdl2_color(color));
+            },
+            None => self.renderer.present()
+        }
+        *self.last_stats.borrow_mut() = self.get_stats();
+        self.reset_stats();
+        if self.hot_reload.get() {
+            self.poll_hot_reload();
+        }
+        self.enforce_texture_budget();
+        self.frame_number.set(self.frame_number.get() + 1);
+        Ok(())
+    }
+
+    // Whether sheets hold on to their decoded surface after the texture is
+    // uploaded (the default). Turning this off drops every loaded sheet's
+    // surface, roughly halving the memory they use.
+    pub fn set_keep_surfaces(&self, keep: bool) {
+        self.keep_surfaces.set(keep);
+        if !keep {
+            for sheet in self.sheets.borrow().values() {
+                sheet.drop_surface();
+            }
+        }
+    }
+
+    // Approximate bytes held by every loaded sheet's texture and surface
+    pub fn get_texture_bytes(&self) -> uint {
+        self.sheets.borrow().values().fold(0, |acc, sheet| acc + sheet.get_bytes())
+    }
+
+    pub fn get_sheet_bytes(&self, name: &String) -> Option<uint> {
+        self.sheets.borrow().get(name).map(|sheet| sheet.get_bytes())
+    }
+
+    // With a budget, present() evicts the least recently drawn sheets until
+    // get_texture_bytes() is back under it; a sheet that hasn't been drawn
+    // yet counts as drawn when it was added. Evicted sheets keep their names
+    // and are loaded again the next time something draws from them. Sheets
+    // that can't be loaded again (from load_sheet_bytes()) are never
+    // evicted, nor are sheets drawn in the frame being presented.
+    pub fn set_texture_budget(&self, bytes: Option<uint>) {
+        self.texture_budget.set(bytes);
+    }
+    pub fn get_texture_budget(&self) -> Option<uint> { self.texture_budget.get() }
+
+    // Evicts sheets as described for set_texture_budget(), returning their
+    // names in eviction order
+    pub fn enforce_texture_budget(&self) -> Vec<String> {
+        let mut evicted = Vec::new();
+        let budget = match self.texture_budget.get() {
+            Some(budget) => budget,
+            None => return evicted
+        };
+        let mut total = self.get_texture_bytes();
+        if total <= budget {
+            return evicted;
+        }
+        let frame = self.frame_number.get();
+        let mut candidates: Vec<Rc<texture::TextureSheet>> = self.sheets.borrow().values()
+            .filter(|sheet| sheet.is_resident() && sheet.get_last_drawn() != frame
+                    && self.can_restore(&***sheet))
+            .map(|sheet| sheet.clone()).collect();
+        candidates.sort_by(|a, b| a.get_last_drawn().cmp(&b.get_last_drawn()));
+        for sheet in candidates.iter() {
+            if total <= budget {
+                break;
+            }
+            total -= sheet.get_bytes();
+            sheet.evict();
+            evicted.push(sheet.get_name().clone());
+        }
+        evicted
+    }
+
+    fn can_restore(&self, sheet: &texture::TextureSheet) -> bool {
+        if sheet.is_on_disk() {
+            return true;
+        }
+        match *self.vfs.borrow() {
+            Some(ref vfs) => vfs.exists(sheet.get_path().as_slice()),
+            None => false
+        }
+    }
+
+    // loads an evicted sheet again, from wherever it first came from
+    fn restore_sheet(&self, sheet: &texture::TextureSheet) -> P2duxResult<()> {
+        if sheet.is_resident() {
+            return Ok(());
+        }
+        if sheet.is_on_disk() {
+            try!(sheet.reload(&*self.renderer));
+        } else {
+            let bytes = match *self.vfs.borrow() {
+                Some(ref vfs) => try!(vfs.read(sheet.get_path().as_slice())),
+                None => return Err(P2duxError::MissingSheet(sheet.get_name().clone()))
+            };
+            try!(sheet.reload_from_bytes(&*self.renderer, bytes.as_slice()));
+        }
+        if !self.keep_surfaces.get() {
+            sheet.drop_surface();
+        }
+        self.stats.borrow_mut().sheet_restores += 1;
+        Ok(())
+    }
+
+    // When on, present() checks the sheets' pngs for changes a couple of
+    // times a second and reloads any that changed. Sheets keep their names,
+    // so SpriteTiles pointing at them carry on working.
+    pub fn set_hot_reload(&self, enabled: bool) {
+        self.hot_reload.set(enabled);
+    }
+
+    // Reloads every sheet whose png changed on disk, returning their names
+    pub fn reload_changed_sheets(&self) -> P2duxResult<Vec<String>> {
+        let mut reloaded = Vec::new();
+        for (name, sheet) in self.sheets.borrow().iter() {
+            if try!(sheet.reload_if_changed(&*self.renderer)) {
+                if !self.keep_surfaces.get() {
+                    sheet.drop_surface();
+                }
+                reloaded.push(name.clone());
+            }
         }
+        Ok(reloaded)
     }
-    pub fn set_draw_sdl2_color(&self, rgb: Color) {
+
+    fn poll_hot_reload(&self) {
+        let now = precise_time_ns() / 1000000;
+        if now < self.next_reload_check.get() {
+            return;
+        }
+        self.next_reload_check.set(now + 500);
+        for sheet in self.sheets.borrow().values() {
+            // a png that won't load yet is most likely still being saved;
+            // the old texture stays up and we try again next time
+            match sheet.reload_if_changed(&*self.renderer) {
+                Ok(true) if !self.keep_surfaces.get() => sheet.drop_surface(),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn set_draw_color(&self, rgb: (u8, u8, u8)) -> P2duxResult<()> {
+        let (r, g, b) = rgb;
+        self.set_draw_sdl2_color(Color::RGB(r, g, b))
+    }
+    pub fn set_draw_sdl2_color(&self, rgb: Color) -> P2duxResult<()> {
+        *self.draw_color.borrow_mut() = rgb.clone();
         match self.renderer.set_draw_color(rgb) {
-            Ok(()) => {},
-            Err(e) => panic!("set_draw_sdl2_color(): failure: {}", e)
+            Ok(()) => Ok(()),
+            Err(e) => Err(P2duxError::RenderCall(format!("set_draw_color(): {}", e)))
         }
     }
 }
 
-impl Drop for GameDisplay {
-    fn drop(&mut self) {
-        // last thing we do
-        sdl2_image::quit();
-        sdl::quit();
+#[cfg(test)]
+mod test {
+    use std::io::TempDir;
+    use sdl2::rect::Rect;
+
+    use super::GameDisplay;
+    use super::texture::TextureSheet;
+    use super::snapshot::Frame;
+
+    static SHEET_BYTES: uint = 8 * 8 * 4;
+
+    // an 8x8 sheet whose png is in `dir`, so it can be evicted and restored
+    fn add(display: &GameDisplay, dir: &TempDir, name: &str) {
+        let path = dir.path().join(format!("{}.png", name));
+        Frame::new(8, 8, Vec::from_elem(SHEET_BYTES, 255u8)).save_png(&path).unwrap();
+        let path = path.display().to_string();
+        let sheet = TextureSheet::new(&*display.renderer, &path, name.to_string()).unwrap();
+        display.add_sheet(sheet);
+    }
+
+    fn draw(display: &GameDisplay, name: &str) {
+        let sheet = display.get_sheet(&name.to_string()).unwrap();
+        let rect = Rect::new(0, 0, 8, 8);
+        display.copy_tile(&*sheet, rect, rect).unwrap();
+    }
+
+    #[test]
+    fn budget_evicts_least_recently_drawn_first() {
+        let dir = TempDir::new("p2dux-budget").unwrap();
+        let display = GameDisplay::new_headless((16, 16), Vec::new()).unwrap();
+        display.set_keep_surfaces(false);
+        add(&display, &dir, "a");
+        add(&display, &dir, "b");
+        display.present().unwrap();
+        draw(&display, "a");
+        display.present().unwrap();
+        // added after "a" was last drawn, though never drawn itself
+        add(&display, &dir, "c");
+        display.present().unwrap();
+        assert_eq!(display.get_texture_bytes(), 3 * SHEET_BYTES);
+
+        display.set_texture_budget(Some(SHEET_BYTES));
+        assert_eq!(display.enforce_texture_budget(), vec!["b".to_string(), "a".to_string()]);
+        assert_eq!(display.get_texture_bytes(), SHEET_BYTES);
+        assert!(display.get_sheet(&"c".to_string()).unwrap().is_resident());
+
+        // drawing an evicted sheet loads it again
+        draw(&display, "a");
+        assert_eq!(display.get_sheet_bytes(&"a".to_string()), Some(SHEET_BYTES));
+    }
+
+    #[test]
+    fn sheets_drawn_this_frame_are_kept() {
+        let dir = TempDir::new("p2dux-budget").unwrap();
+        let display = GameDisplay::new_headless((16, 16), Vec::new()).unwrap();
+        display.set_keep_surfaces(false);
+        add(&display, &dir, "a");
+        display.present().unwrap();
+        draw(&display, "a");
+        display.set_texture_budget(Some(0));
+        assert!(display.enforce_texture_budget().is_empty());
+        assert_eq!(display.get_texture_bytes(), SHEET_BYTES);
     }
 }
@@ -0,0 +1,315 @@
+// Copyright 2013-2014 Jeffery Olson
+//
+// Licensed under the 3-Clause BSD License, see LICENSE.txt
+// at the top-level of this repository.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::io;
+use std::io::fs;
+use std::io::fs::PathExtensions;
+use std::u16;
+
+use {Clock, SystemClock};
+use error::{P2duxError, P2duxResult};
+use super::GameDisplay;
+use super::gif::GifWriter;
+use super::snapshot::Frame;
+
+// shorter gif delays get slowed down to 10cs by most viewers
+static MIN_DELAY_CS: u64 = 2;
+
+pub enum RecordFormat {
+    // frame-00000.png, frame-00001.png, ... in the given directory
+    PngSequence(Path),
+    // a single looping animated gif at the given path
+    Gif(Path)
+}
+
+// Captures every `every_nth` presented frame, stopping by itself after
+// `duration_ms` (if given). Start one with GameDisplay::start_recording().
+pub struct FrameRecorder {
+    format: RecordFormat,
+    every_nth: uint,
+    duration_ms: Option<u64>,
+    clock: Box<Clock + 'static>,
+    presented: uint,
+    captured: uint,
+    started_ms: Option<u64>,
+    gif: Option<GifWriter>,
+    // gif frames are held back one capture, to learn how long they stay up
+    pending: Option<(Frame, u64)>,
+    timing: GifTiming
+}
+
+// Turns the times gif frames were shown at into their delays. GIF delays
+// are whole hundredths of a second, so what's left over of each frame's ms
+// is carried into the next one rather than dropped, and a frame that hasn't
+// been up for MIN_DELAY_CS yet is merged with the one after it.
+struct GifTiming {
+    carry_ms: u64,
+    last_delay_cs: Option<u16>
+}
+
+impl GifTiming {
+    fn new() -> GifTiming {
+        GifTiming { carry_ms: 0, last_delay_cs: None }
+    }
+
+    // how long the frame shown at `shown_ms` stays up now that another one
+    // was shown at `now`, or None if it's too soon and the two are merged
+    fn delay_cs(&mut self, shown_ms: u64, now: u64) -> Option<u16> {
+        let elapsed = if now > shown_ms { now - shown_ms } else { 0 } + self.carry_ms;
+        let delay = elapsed / 10;
+        if delay < MIN_DELAY_CS {
+            return None;
+        }
+        let delay = if delay > u16::MAX as u64 {
+            self.carry_ms = 0;
+            u16::MAX
+        } else {
+            self.carry_ms = elapsed % 10;
+            delay as u16
+        };
+        self.last_delay_cs = Some(delay);
+        Some(delay)
+    }
+}
+
+impl FrameRecorder {
+    pub fn new(format: RecordFormat, every_nth: uint, duration_ms: Option<u64>) -> FrameRecorder {
+        FrameRecorder::with_clock(format, every_nth, duration_ms, box SystemClock)
+    }
+
+    // times frames (and the duration) by `clock`, e.g. ViewContext::get_clock()
+    // so a scripted run records at its scripted pace
+    pub fn with_clock(format: RecordFormat, every_nth: uint, duration_ms: Option<u64>,
+                      clock: Box<Clock + 'static>) -> FrameRecorder {
+        FrameRecorder {
+            format: format,
+            every_nth: if every_nth == 0 { 1 } else { every_nth },
+            duration_ms: duration_ms,
+            clock: clock,
+            presented: 0,
+            captured: 0,
+            started_ms: None,
+            gif: None,
+            pending: None,
+            timing: GifTiming::new()
+        }
+    }
+
+    // frames written out so far; gif frames merged into another don't count
+    pub fn get_captured(&self) -> uint { self.captured }
+
+    // Called with each frame about to be presented. Returns true once the
+    // recording's duration is up.
+    pub fn on_present(&mut self, display: &GameDisplay) -> P2duxResult<bool> {
+        let now = self.clock.now_ms();
+        let started = match self.started_ms {
+            Some(t) => t,
+            None => {
+                try!(self.prepare_output());
+                self.started_ms = Some(now);
+                now
+            }
+        };
+        match self.duration_ms {
+            Some(d) if now - started >= d => return Ok(true),
+            _ => {}
+        }
+        self.presented += 1;
+        if (self.presented - 1) % self.every_nth != 0 {
+            return Ok(false);
+        }
+        let frame = try!(display.read_frame());
+        try!(self.capture(frame, now));
+        Ok(false)
+    }
+
+    // Writes out anything still buffered; returns the number of frames
+    pub fn finish(mut self) -> P2duxResult<uint> {
+        match self.pending.take() {
+            Some((frame, _)) => {
+                // no later frame to measure against, so reuse the last gap
+                let delay = self.last_delay_cs();
+                try!(self.write_gif_frame(&frame, delay));
+            },
+            None => {}
+        }
+        match self.gif.take() {
+            Some(gif) => try!(gif.finish()),
+            None => {}
+        }
+        Ok(self.captured)
+    }
+
+    fn prepare_output(&self) -> P2duxResult<()> {
+        let dir = match self.format {
+            RecordFormat::PngSequence(ref dir) => dir.clone(),
+            RecordFormat::Gif(ref path) => path.dir_path()
+        };
+        if dir.exists() {
+            return Ok(());
+        }
+        match fs::mkdir_recursive(&dir, io::USER_RWX) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(P2duxError::Io(dir.display().to_string(), e.to_string()))
+        }
+    }
+
+    fn capture(&mut self, frame: Frame, now: u64) -> P2duxResult<()> {
+        match self.format {
+            RecordFormat::PngSequence(ref dir) => {
+                let idx = self.captured;
+                self.captured += 1;
+                return frame.save_png(&dir.join(format!("frame-{:05}.png", idx)));
+            },
+            RecordFormat::Gif(_) => {}
+        }
+        match self.pending.take() {
+            Some((prev, prev_time)) => match self.timing.delay_cs(prev_time, now) {
+                Some(delay) => try!(self.write_gif_frame(&prev, delay)),
+                None => {
+                    // too soon after prev; it stays up in this frame's place
+                    self.pending = Some((prev, prev_time));
+                    return Ok(());
+                }
+            },
+            None => {}
+        }
+        self.pending = Some((frame, now));
+        Ok(())
+    }
+
+    fn write_gif_frame(&mut self, frame: &Frame, delay_cs: u16) -> P2duxResult<()> {
+        if self.gif.is_none() {
+            let path = match self.format {
+                RecordFormat::Gif(ref path) => path.clone(),
+                RecordFormat::PngSequence(_) => return Ok(())
+            };
+            self.gif = Some(try!(GifWriter::create(&path, frame.width, frame.height)));
+        }
+        self.captured += 1;
+        match self.gif {
+            Some(ref mut gif) => gif.write_frame(frame, delay_cs),
+            None => Ok(())
+        }
+    }
+
+    fn last_delay_cs(&self) -> u16 {
+        match self.timing.last_delay_cs {
+            Some(delay) => delay,
+            None => {
+                // assume 60fps when there's nothing better to go on
+                let frame_ms = 1000 / 60;
+                let delay = (frame_ms * self.every_nth / 10) as u64;
+                if delay < MIN_DELAY_CS { MIN_DELAY_CS as u16 } else { delay as u16 }
+            }
+        }
+    }
+}
+
+impl GameDisplay {
+    // Replaces any recording already in progress, finishing it first
+    pub fn start_recording(&self, recorder: FrameRecorder) -> P2duxResult<()> {
+        try!(self.stop_recording());
+        *self.recorder.borrow_mut() = Some(recorder);
+        Ok(())
+    }
+
+    // Finishes the current recording, if any, returning how many frames it
+    // captured
+    pub fn stop_recording(&self) -> P2duxResult<uint> {
+        match self.recorder.borrow_mut().take() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(0)
+        }
+    }
+
+    pub fn is_recording(&self) -> bool { self.recorder.borrow().is_some() }
+
+    // When writing a frame fails during present(), recording stops and the
+    // error is kept here; returns it, if any, clearing it
+    pub fn take_recording_error(&self) -> Option<P2duxError> {
+        self.recording_error.borrow_mut().take()
+    }
+
+    // present() hands each frame to the recorder here before showing it
+    pub fn record_frame(&self) -> P2duxResult<()> {
+        let done = match *self.recorder.borrow_mut() {
+            Some(ref mut recorder) => try!(recorder.on_present(self)),
+            None => false
+        };
+        if done {
+            try!(self.stop_recording());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::TempDir;
+    use std::io::fs::PathExtensions;
+    use sdl2::pixels::Color;
+
+    use ManualClock;
+    use gfx::GameDisplay;
+    use super::{GifTiming, FrameRecorder, RecordFormat, MIN_DELAY_CS};
+
+    // the delays written for frames shown at each of `times`
+    fn delays(times: &[u64]) -> Vec<u16> {
+        let mut timing = GifTiming::new();
+        let mut delays = Vec::new();
+        let mut shown = times[0];
+        for now in times.slice_from(1).iter() {
+            match timing.delay_cs(shown, *now) {
+                Some(delay) => {
+                    delays.push(delay);
+                    shown = *now;
+                },
+                None => {}
+            }
+        }
+        delays
+    }
+
+    #[test]
+    fn sixty_fps_keeps_time() {
+        // one second at 60fps, as whole ms
+        let times: Vec<u64> = range(0u64, 61).map(|i| i * 1000 / 60).collect();
+        let delays = delays(times.as_slice());
+        assert!(delays.iter().all(|d| *d as u64 >= MIN_DELAY_CS));
+        assert_eq!(delays.iter().fold(0u, |sum, d| sum + *d as uint), 100);
+    }
+
+    #[test]
+    fn leftover_ms_carry_over() {
+        assert_eq!(delays(&[0, 25, 50, 75, 100]), vec![2, 3, 2, 3]);
+    }
+
+    #[test]
+    fn short_frames_are_merged() {
+        assert_eq!(delays(&[0, 5, 10, 15, 20, 40]), vec![2, 2]);
+    }
+
+    #[test]
+    fn records_by_its_clock() {
+        let dir = TempDir::new("p2dux-record").unwrap();
+        let path = dir.path().join("out.gif");
+        let display = GameDisplay::new_headless((16, 16), Vec::new()).unwrap();
+        let clock = ManualClock::new(0);
+        let recorder = FrameRecorder::with_clock(RecordFormat::Gif(path.clone()), 1, Some(100),
+                                                 box clock.clone());
+        display.start_recording(recorder).unwrap();
+        // 60fps: every other frame is merged into the one before it
+        while display.is_recording() {
+            display.clear_with(Color::RGB(0, 0, 0)).unwrap();
+            display.present().unwrap();
+            clock.advance(1000 / 60);
+        }
+        assert!(display.take_recording_error().is_none());
+        assert!(path.exists());
+    }
+}
@@ -0,0 +1,300 @@
+// Copyright 2013-2014 Jeffery Olson
+//
+// Licensed under the 3-Clause BSD License, see LICENSE.txt
+// at the top-level of this repository.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::vec::Vec;
+use std::cmp::max;
+use std::io::fs::PathExtensions;
+
+use sdl2;
+use sdl2::surface::Surface;
+use sdl2::pixels::PixelFormatFlag;
+use sdl2::render::BlendMode;
+use sdl2::rwops::RWops;
+use sdl2_image::{LoadSurface, SaveSurface, ImageRWops};
+
+use error::{P2duxError, P2duxResult};
+use super::GameDisplay;
+
+// channel masks for tightly packed RGBA bytes (R first in memory)
+static RMASK: u32 = 0x000000ff;
+static GMASK: u32 = 0x0000ff00;
+static BMASK: u32 = 0x00ff0000;
+static AMASK: u32 = 0xff000000;
+
+// A read-back frame, stored as tightly packed RGBA bytes, row by row.
+#[deriving(Clone)]
+pub struct Frame {
+    pub width: uint,
+    pub height: uint,
+    pub pixels: Vec<u8>
+}
+
+pub enum SnapshotResult {
+    // frame is within tolerance of the reference
+    Matched,
+    // no reference existed yet, so the frame was saved as the new reference
+    Recorded,
+    // reference has different dimensions than the frame
+    SizeMismatch((uint, uint), (uint, uint)),
+    // count of pixels outside tolerance, and where the diff image was saved
+    Mismatched(uint, Path)
+}
+
+impl Frame {
+    pub fn new(width: uint, height: uint, pixels: Vec<u8>) -> Frame {
+        Frame { width: width, height: height, pixels: pixels }
+    }
+
+    pub fn get_pixel(&self, x: uint, y: uint) -> (u8, u8, u8, u8) {
+        let i = (y * self.width + x) * 4;
+        let p = &self.pixels;
+        (p[i], p[i+1], p[i+2], p[i+3])
+    }
+
+    pub fn load_png(path: &Path) -> P2duxResult<Frame> {
+        let loaded: Surface = match LoadSurface::from_file(path) {
+            Ok(s) => s,
+            Err(msg) => return Err(P2duxError::ImageLoad(path.display().to_string(), msg))
+        };
+        Frame::from_loaded(loaded)
+    }
+
+    // for a png that's already in memory; `source` names it in errors
+    pub fn load_png_bytes(bytes: &[u8], source: &str) -> P2duxResult<Frame> {
+        let rwops = match RWops::from_bytes(bytes) {
+            Ok(r) => r,
+            Err(msg) => return Err(P2duxError::ImageLoad(source.to_string(), msg))
+        };
+        let loaded = match rwops.load() {
+            Ok(s) => s,
+            Err(msg) => return Err(P2duxError::ImageLoad(source.to_string(), msg))
+        };
+        Frame::from_loaded(loaded)
+    }
+
+    fn from_loaded(mut loaded: Surface) -> P2duxResult<Frame> {
+        // blit onto an RGBA surface so we don't care what format the png was in
+        let (w, h) = (loaded.get_width(), loaded.get_height());
+        let mut rgba = match Surface::new(sdl2::surface::SWSURFACE, w, h, 32,
+                                          RMASK, GMASK, BMASK, AMASK) {
+            Ok(s) => s,
+            Err(msg) => return Err(P2duxError::RenderCall(
+                format!("Frame::from_loaded(): couldn't create surface: {}", msg)))
+        };
+        loaded.set_blend_mode(BlendMode::None);
+        rgba.blit(&loaded, None, None);
+        let (w, h) = (w as uint, h as uint);
+        let pitch = rgba.get_pitch() as uint;
+        let pixels = rgba.with_lock(|raw| {
+            let mut pixels = Vec::with_capacity(w * h * 4);
+            for y in range(0, h) {
+                pixels.push_all(raw.slice(y * pitch, y * pitch + w * 4));
+            }
+            pixels
+        });
+        Ok(Frame::new(w, h, pixels))
+    }
+
+    // copies the pixels into a new RGBA surface that owns them
+    pub fn to_surface(&self) -> P2duxResult<Surface> {
+        let surface = Surface::new(sdl2::surface::SWSURFACE, self.width as int, self.height as int,
+                                   32, RMASK, GMASK, BMASK, AMASK);
+        let surface = match surface {
+            Ok(s) => s,
+            Err(msg) => return Err(P2duxError::RenderCall(
+                format!("Frame::to_surface(): couldn't create surface: {}", msg)))
+        };
+        let pitch = surface.get_pitch() as uint;
+        let row = self.width * 4;
+        surface.with_lock(|raw| {
+            for y in range(0, self.height) {
+                let src = self.pixels.slice(y * row, (y + 1) * row);
+                raw.slice_mut(y * pitch, y * pitch + row).clone_from_slice(src);
+            }
+        });
+        Ok(surface)
+    }
+
+    pub fn save_png(&self, path: &Path) -> P2duxResult<()> {
+        let mut data = self.pixels.clone();
+        let surface = Surface::from_data(
+            data.as_mut_slice(), self.width as int, self.height as int, 32,
+            (self.width * 4) as int, RMASK, GMASK, BMASK, AMASK);
+        let surface = match surface {
+            Ok(s) => s,
+            Err(msg) => return Err(P2duxError::RenderCall(
+                format!("Frame::save_png(): couldn't create surface: {}", msg)))
+        };
+        match surface.save(path) {
+            Ok(()) => Ok(()),
+            Err(msg) => Err(P2duxError::ImageSave(path.display().to_string(), msg))
+        }
+    }
+
+    // Compares against another frame of the same size. A pixel mismatches
+    // when any of its channels differs by more than `tolerance`. Returns the
+    // mismatch count and a diff image: mismatches in solid red, everything
+    // else as a faded grayscale copy of this frame.
+    pub fn diff(&self, other: &Frame, tolerance: u8) -> (uint, Frame) {
+        let mut mismatched = 0;
+        let mut diff = Vec::with_capacity(self.pixels.len());
+        for (a, b) in self.pixels.as_slice().chunks(4).zip(other.pixels.as_slice().chunks(4)) {
+            let mut worst = 0u8;
+            for c in range(0u, 4) {
+                let d = if a[c] > b[c] { a[c] - b[c] } else { b[c] - a[c] };
+                worst = max(worst, d);
+            }
+            if worst > tolerance {
+                mismatched += 1;
+                diff.push_all(&[255u8, 0, 0, 255]);
+            } else {
+                let lum = ((a[0] as uint + a[1] as uint + a[2] as uint) / 3) as u8;
+                let faded = 128 + (lum >> 2);
+                diff.push_all(&[faded, faded, faded, 255]);
+            }
+        }
+        (mismatched, Frame::new(self.width, self.height, diff))
+    }
+
+    // Checks this frame against the reference png at `reference`. When there
+    // is no reference yet, this frame is saved as the reference. On mismatch
+    // the diff and the actual frame are written next to the reference, as
+    // `<name>.diff.png` and `<name>.actual.png`.
+    pub fn check_against(&self, reference: &Path, tolerance: u8) -> P2duxResult<SnapshotResult> {
+        if !reference.exists() {
+            try!(self.save_png(reference));
+            return Ok(SnapshotResult::Recorded);
+        }
+        let expected = try!(Frame::load_png(reference));
+        if expected.width != self.width || expected.height != self.height {
+            return Ok(SnapshotResult::SizeMismatch(
+                (expected.width, expected.height), (self.width, self.height)));
+        }
+        let (mismatched, diff) = self.diff(&expected, tolerance);
+        if mismatched == 0 {
+            return Ok(SnapshotResult::Matched);
+        }
+        let diff_path = reference.with_extension("diff.png");
+        try!(diff.save_png(&diff_path));
+        try!(self.save_png(&reference.with_extension("actual.png")));
+        Ok(SnapshotResult::Mismatched(mismatched, diff_path))
+    }
+}
+
+impl GameDisplay {
+    // Reads back whatever has been drawn so far this frame, flushing any
+    // batched draws first. Call before `present()`, as the back buffer is
+    // undefined afterwards. This reads whatever is being drawn to: the bound
+    // RenderTarget, else the logical image (unscaled), else the window.
+    pub fn read_frame(&self) -> P2duxResult<Frame> {
+        try!(self.flush());
+        let (w, h) = match (self.target_size.get(), self.get_logical_size()) {
+            (Some(size), _) => size,
+            (None, Some(size)) => size,
+            (None, None) => match self.renderer.get_output_size() {
+                Ok((w, h)) => (w as uint, h as uint),
+                Err(e) => return Err(P2duxError::RenderCall(
+                    format!("read_frame(): couldn't get output size: {}", e)))
+            }
+        };
+        let pixels = match self.renderer.read_pixels(None, PixelFormatFlag::ABGR8888) {
+            Ok(p) => p.as_slice().to_vec(),
+            Err(e) => return Err(P2duxError::RenderCall(
+                format!("read_frame(): failure in read_pixels(): {}", e)))
+        };
+        if pixels.len() != w * h * 4 {
+            return Err(P2duxError::RenderCall(
+                format!("read_frame(): expected {}x{} pixels but read {} bytes", w, h, pixels.len())));
+        }
+        Ok(Frame::new(w, h, pixels))
+    }
+
+    // Saves the current back buffer as a png; same caveats as read_frame()
+    pub fn screenshot(&self, path: &Path) -> P2duxResult<()> {
+        let frame = try!(self.read_frame());
+        frame.save_png(path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::TempDir;
+    use std::io::fs::PathExtensions;
+    use sdl2::pixels::Color;
+
+    use gfx::GameDisplay;
+    use super::{Frame, SnapshotResult};
+
+    fn solid(w: uint, h: uint, rgba: (u8, u8, u8, u8)) -> Frame {
+        let (r, g, b, a) = rgba;
+        let mut pixels = Vec::with_capacity(w * h * 4);
+        for _ in range(0, w * h) {
+            pixels.push_all(&[r, g, b, a]);
+        }
+        Frame::new(w, h, pixels)
+    }
+
+    #[test]
+    fn diff_within_tolerance() {
+        let a = solid(4, 4, (100, 100, 100, 255));
+        let b = solid(4, 4, (104, 97, 100, 255));
+        let (within, _) = a.diff(&b, 4);
+        assert_eq!(within, 0);
+        let (outside, _) = a.diff(&b, 3);
+        assert_eq!(outside, 16);
+    }
+
+    #[test]
+    fn diff_marks_mismatches_red() {
+        let a = solid(2, 1, (0, 0, 0, 255));
+        let mut b = a.clone();
+        b.pixels[4] = 200;
+        let (mismatched, diff) = a.diff(&b, 8);
+        assert_eq!(mismatched, 1);
+        assert_eq!(diff.get_pixel(1, 0), (255, 0, 0, 255));
+        assert!(diff.get_pixel(0, 0) != (255, 0, 0, 255));
+    }
+
+    #[test]
+    fn headless_read_frame() {
+        let display = GameDisplay::new_headless((32, 24), Vec::new()).unwrap();
+        display.clear_with(Color::RGB(10, 20, 30)).unwrap();
+        let frame = display.read_frame().unwrap();
+        assert_eq!((frame.width, frame.height), (32, 24));
+        assert_eq!(frame.pixels.len(), 32 * 24 * 4);
+        assert_eq!(frame.get_pixel(31, 23), (10, 20, 30, 255));
+    }
+
+    #[test]
+    fn check_against_records_then_compares() {
+        let dir = TempDir::new("p2dux-snapshot").unwrap();
+        let reference = dir.path().join("frame.png");
+        let frame = solid(8, 8, (50, 60, 70, 255));
+        match frame.check_against(&reference, 0).unwrap() {
+            SnapshotResult::Recorded => {},
+            _ => panic!("expected the reference to be recorded")
+        }
+        match frame.check_against(&reference, 0).unwrap() {
+            SnapshotResult::Matched => {},
+            _ => panic!("expected a match against the recorded reference")
+        }
+        match solid(8, 8, (50, 60, 90, 255)).check_against(&reference, 10).unwrap() {
+            SnapshotResult::Mismatched(count, diff_path) => {
+                assert_eq!(count, 64);
+                assert!(diff_path.exists());
+            },
+            _ => panic!("expected a mismatch")
+        }
+        match solid(4, 8, (50, 60, 70, 255)).check_against(&reference, 0).unwrap() {
+            SnapshotResult::SizeMismatch(expected, actual) => {
+                assert_eq!(expected, (8, 8));
+                assert_eq!(actual, (4, 8));
+            },
+            _ => panic!("expected a size mismatch")
+        }
+    }
+}
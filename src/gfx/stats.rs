@@ -0,0 +1,45 @@
+// Copyright 2013-2014 Jeffery Olson
+//
+// Licensed under the 3-Clause BSD License, see LICENSE.txt
+// at the top-level of this repository.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::HashMap;
+
+// Per-frame accounting of what was sent to the renderer through
+// GameDisplay. A texture switch is a tile copy from a different sheet than
+// the copy before it.
+#[deriving(Clone, Show)]
+pub struct RenderStats {
+    pub tile_copies: HashMap<String, uint>,
+    pub fill_rects: uint,
+    pub texture_switches: uint,
+    pub failed_copies: uint,
+    // evicted sheets loaded again to be drawn
+    pub sheet_restores: uint
+}
+
+impl RenderStats {
+    pub fn new() -> RenderStats {
+        RenderStats {
+            tile_copies: HashMap::new(),
+            fill_rects: 0,
+            texture_switches: 0,
+            failed_copies: 0,
+            sheet_restores: 0
+        }
+    }
+
+    pub fn total_tile_copies(&self) -> uint {
+        self.tile_copies.values().fold(0, |acc, n| acc + *n)
+    }
+
+    pub fn record_tile_copy(&mut self, sheet: &String) {
+        let count = match self.tile_copies.get_mut(sheet) {
+            Some(count) => { *count += 1; return; },
+            None => 1u
+        };
+        self.tile_copies.insert(sheet.clone(), count);
+    }
+}
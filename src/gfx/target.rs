@@ -0,0 +1,146 @@
+// Copyright 2013-2014 Jeffery Olson
+//
+// Licensed under the 3-Clause BSD License, see LICENSE.txt
+// at the top-level of this repository.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use sdl2::rect::Rect;
+use sdl2::pixels::{Color, PixelFormatFlag};
+use sdl2::render::{Texture, TextureAccess, BlendMode};
+
+use error::{P2duxError, P2duxResult};
+use super::GameDisplay;
+
+// An offscreen texture that can be drawn into with all the usual calls
+// (draw_tile, UiBox::draw_box, UiFont::draw_line, ...) between
+// begin_target() and end_target(), then drawn itself with draw_target().
+// Starts out fully transparent. Targets don't nest: there's no going back
+// to one target after beginning another, so begin_target() and
+// create_render_target() are errors until end_target().
+pub struct RenderTarget {
+    texture: Texture,
+    size: (uint, uint)
+}
+
+impl RenderTarget {
+    pub fn get_size(&self) -> (uint, uint) { self.size }
+}
+
+impl GameDisplay {
+    pub fn create_render_target(&self, size: (uint, uint)) -> P2duxResult<RenderTarget> {
+        // clearing the new texture means binding it, which would lose the
+        // target being drawn to
+        try!(self.check_not_on_target("create_render_target()"));
+        let (w, h) = size;
+        let texture = self.renderer.create_texture(
+            PixelFormatFlag::ARGB8888, TextureAccess::Target, w as int, h as int);
+        let texture = match texture {
+            Ok(t) => t,
+            Err(e) => return Err(P2duxError::RenderCall(
+                format!("create_render_target(): couldn't create texture: {}", e)))
+        };
+        match texture.set_blend_mode(BlendMode::Blend) {
+            Ok(()) => {},
+            Err(e) => return Err(P2duxError::RenderCall(
+                format!("create_render_target(): set_blend_mode(): {}", e)))
+        }
+        let target = RenderTarget { texture: texture, size: size };
+        try!(self.begin_target(&target));
+        try!(self.clear_with(Color::RGBA(0, 0, 0, 0)));
+        try!(self.end_target());
+        Ok(target)
+    }
+
+    // Send all drawing to `target` until end_target()
+    pub fn begin_target(&self, target: &RenderTarget) -> P2duxResult<()> {
+        try!(self.check_not_on_target("begin_target()"));
+        // batched draws so far belong to whatever was bound before
+        try!(self.flush());
+        try!(self.bind_target(Some(&target.texture)));
+        self.target_size.set(Some(target.size));
+        Ok(())
+    }
+
+    // Back to drawing to the screen (or the logical image, if there is one)
+    pub fn end_target(&self) -> P2duxResult<()> {
+        try!(self.flush());
+        self.target_size.set(None);
+        match *self.logical.borrow() {
+            Some(ref logical) => self.bind_target(Some(&logical.texture)),
+            None => self.bind_target(None)
+        }
+    }
+
+    pub fn is_on_target(&self) -> bool { self.target_size.get().is_some() }
+
+    fn check_not_on_target(&self, call: &str) -> P2duxResult<()> {
+        if self.is_on_target() {
+            return Err(P2duxError::RenderCall(
+                format!("{}: already drawing to a render target; call end_target() first", call)));
+        }
+        Ok(())
+    }
+
+    // Clears whatever is currently being drawn to, render target or not
+    pub fn clear_with(&self, color: Color) -> P2duxResult<()> {
+        let prev = self.draw_color.borrow().clone();
+        try!(self.set_draw_sdl2_color(color));
+        let result = match self.renderer.clear() {
+            Ok(()) => Ok(()),
+            Err(e) => Err(P2duxError::RenderCall(format!("clear_with(): {}", e)))
+        };
+        try!(self.set_draw_sdl2_color(prev));
+        result
+    }
+
+    // Draws the target's contents with their top-left at `coords`, stretched
+    // to `size` if given. This isn't batched: anything queued so far is
+    // flushed first so it ends up beneath.
+    pub fn draw_target(&self, target: &RenderTarget, coords: (int, int),
+                       size: Option<(uint, uint)>) -> P2duxResult<()> {
+        let (x, y) = coords;
+        let (w, h) = match size {
+            Some(s) => s,
+            None => target.size
+        };
+        let dst = match self.place_on_layer(Rect::new(x as i32, y as i32, w as i32, h as i32)) {
+            Some(dst) => dst,
+            None => return Ok(())
+        };
+        try!(self.flush());
+        match self.renderer.copy(&target.texture, None, Some(dst)) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(P2duxError::RenderCall(format!("draw_target(): copy failed: {}", e)))
+        }
+    }
+
+    fn bind_target(&self, texture: Option<&Texture>) -> P2duxResult<()> {
+        match self.renderer.set_render_target(texture) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(P2duxError::RenderCall(format!("set_render_target(): {}", e)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gfx::GameDisplay;
+
+    #[test]
+    fn targets_dont_nest() {
+        let display = GameDisplay::new_headless((32, 32), Vec::new()).unwrap();
+        let outer = display.create_render_target((8, 8)).unwrap();
+        let inner = display.create_render_target((4, 4)).unwrap();
+        display.begin_target(&outer).unwrap();
+        assert!(display.begin_target(&inner).is_err());
+        assert!(display.create_render_target((4, 4)).is_err());
+        // still drawing to the outer target
+        assert!(display.is_on_target());
+        assert_eq!(display.read_frame().unwrap().width, 8);
+        display.end_target().unwrap();
+        assert!(!display.is_on_target());
+        display.begin_target(&inner).unwrap();
+        display.end_target().unwrap();
+    }
+}
@@ -8,49 +8,238 @@
 use std::result::{Ok, Err};
 use std::option::{Some};
 use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::io::fs;
 
 use sdl2::surface::{Surface};
 use sdl2::render::{Renderer, Texture};
 use sdl2::rect::{Rect};
 use sdl2::video::Window;
-use sdl2_image::LoadSurface;
+use sdl2::rwops::RWops;
+use sdl2_image::{LoadSurface, ImageRWops};
 
 use p2d::sprite::SpriteTile;
 
+use error::{P2duxError, P2duxResult};
+
 pub struct TextureSheet {
     name: String,
-    surface: Box<Surface>,
-    texture: Box<Texture>
+    path: String,
+    // mtime of the png when it was last loaded, for hot reloading
+    modified: Cell<u64>,
+    // false for sheets made from bytes, whose path isn't a file on disk
+    on_disk: bool,
+    // pixel size of the image, kept while evicted for memory accounting
+    size: Cell<(uint, uint)>,
+    // GameDisplay frame number this sheet was last drawn in
+    last_drawn: Cell<u64>,
+    // the decoded image; only needed to upload the texture, so it can be
+    // dropped after that to save memory
+    surface: RefCell<Option<Box<Surface>>>,
+    // None while evicted
+    texture: RefCell<Option<Box<Texture>>>
+}
+
+pub type TextureSheets = HashMap<String, Rc<TextureSheet>>;
+
+// Keeps a sheet loaded through GameDisplay::load_sheet() in use. Once every
+// handle to a sheet is dropped, GameDisplay::release_unused_sheets() will
+// unload it.
+#[deriving(Clone)]
+pub struct SheetHandle {
+    sheet: Rc<TextureSheet>
 }
 
-pub type TextureSheets = HashMap<String, TextureSheet>;
+impl SheetHandle {
+    pub fn new(sheet: Rc<TextureSheet>) -> SheetHandle {
+        SheetHandle { sheet: sheet }
+    }
+    pub fn get_name<'a>(&'a self) -> &'a String { self.sheet.get_name() }
+    pub fn get_sheet<'a>(&'a self) -> &'a TextureSheet { &*self.sheet }
+}
 
 impl TextureSheet {
-    pub fn new(renderer: &Renderer, path_str: &String, name: String) -> TextureSheet {
-        let path = Path::new(path_str.as_slice());
-        let surface = match LoadSurface::from_file(&path) {
-            Ok(s) => s,
-            Err(msg) => panic!(format!("new_sprite_from: Couldn't create texture from path '{}', msg: {}", path_str, msg))
-        };
+    pub fn new(renderer: &Renderer, path_str: &String, name: String) -> P2duxResult<TextureSheet> {
+        let modified = modified_time(path_str);
+        let (surface, texture) = try!(load(renderer, path_str));
+        Ok(TextureSheet::build(name, path_str.clone(), modified, true, surface, texture))
+    }
+
+    // For images that were decoded elsewhere. `path_str` is still where the
+    // png lives, so hot reloading keeps working when it's `on_disk` rather
+    // than in a vfs.
+    pub fn from_surface(renderer: &Renderer, surface: Surface, path_str: &String,
+                        name: String, on_disk: bool) -> P2duxResult<TextureSheet> {
         let surface = box surface;
         let texture = match renderer.create_texture_from_surface(&*surface) {
             Ok(t) => t,
-            Err(msg) => panic!(format!("new_sprite_from: Couldn't create texture from path '{}', msg: {}", path_str, msg))
+            Err(msg) => return Err(P2duxError::ImageLoad(path_str.clone(), msg))
         };
-        TextureSheet { name: name,
-                     surface: surface, texture: box texture }
+        let modified = if on_disk { modified_time(path_str) } else { 0 };
+        Ok(TextureSheet::build(name, path_str.clone(), modified, on_disk,
+                               surface, box texture))
+    }
+
+    // Decodes a png (or anything else sdl2_image reads) that's already in
+    // memory. `path_str` is only used to name the sheet's source in errors;
+    // sheets made this way are never hot reloaded.
+    pub fn from_bytes(renderer: &Renderer, bytes: &[u8], path_str: &String,
+                      name: String) -> P2duxResult<TextureSheet> {
+        let (surface, texture) = try!(load_bytes(renderer, bytes, path_str));
+        Ok(TextureSheet::build(name, path_str.clone(), 0, false, surface, texture))
+    }
+
+    fn build(name: String, path: String, modified: u64, on_disk: bool,
+             surface: Box<Surface>, texture: Box<Texture>) -> TextureSheet {
+        TextureSheet { name: name, path: path,
+                     modified: Cell::new(modified), on_disk: on_disk,
+                     size: Cell::new(surface_size(&*surface)), last_drawn: Cell::new(0),
+                     surface: RefCell::new(Some(surface)), texture: RefCell::new(Some(texture)) }
+    }
+
+    pub fn get_name<'a>(&'a self) -> &'a String { &self.name }
+    pub fn get_path<'a>(&'a self) -> &'a String { &self.path }
+    pub fn is_on_disk(&self) -> bool { self.on_disk }
+    pub fn get_size(&self) -> (uint, uint) { self.size.get() }
+
+    // whether the texture is loaded, i.e. the sheet isn't evicted
+    pub fn is_resident(&self) -> bool { self.texture.borrow().is_some() }
+    pub fn has_surface(&self) -> bool { self.surface.borrow().is_some() }
+
+    // Approximate memory held for this sheet, counting 4 bytes a pixel for
+    // the texture and again for the surface, if either is loaded
+    pub fn get_bytes(&self) -> uint {
+        let (w, h) = self.size.get();
+        let mut copies = 0;
+        if self.is_resident() { copies += 1; }
+        if self.has_surface() { copies += 1; }
+        w * h * 4 * copies
+    }
+
+    pub fn get_last_drawn(&self) -> u64 { self.last_drawn.get() }
+    pub fn mark_drawn(&self, frame: u64) { self.last_drawn.set(frame); }
+
+    pub fn drop_surface(&self) {
+        *self.surface.borrow_mut() = None;
+    }
+
+    // Frees the texture and surface; the sheet can't be drawn until it's
+    // restored with reload() or reload_from_bytes()
+    pub fn evict(&self) {
+        *self.texture.borrow_mut() = None;
+        self.drop_surface();
+    }
+
+    // Loads the png at the sheet's path again
+    pub fn reload(&self, renderer: &Renderer) -> P2duxResult<()> {
+        let modified = modified_time(&self.path);
+        let (surface, texture) = try!(load(renderer, &self.path));
+        self.replace(surface, texture);
+        self.modified.set(modified);
+        Ok(())
+    }
+
+    pub fn reload_from_bytes(&self, renderer: &Renderer, bytes: &[u8]) -> P2duxResult<()> {
+        let (surface, texture) = try!(load_bytes(renderer, bytes, &self.path));
+        self.replace(surface, texture);
+        Ok(())
+    }
+
+    fn replace(&self, surface: Box<Surface>, texture: Box<Texture>) {
+        self.size.set(surface_size(&*surface));
+        *self.surface.borrow_mut() = Some(surface);
+        *self.texture.borrow_mut() = Some(texture);
+    }
+
+    // Reloads the png if it has been modified since it was last loaded,
+    // returning whether it was. When the new png can't be loaded (say it's
+    // still being written) the old texture is kept and the next call tries
+    // again. Evicted sheets are skipped, they'll be loaded fresh when next
+    // drawn.
+    pub fn reload_if_changed(&self, renderer: &Renderer) -> P2duxResult<bool> {
+        if !self.on_disk || !self.is_resident() {
+            return Ok(false);
+        }
+        if modified_time(&self.path) == self.modified.get() {
+            return Ok(false);
+        }
+        try!(self.reload(renderer));
+        Ok(true)
     }
 
     pub fn draw_tile(&self, renderer: &Renderer, st: &SpriteTile,
-                     dst_coords: (int, int), dst_size: (uint, uint)) -> bool {
-        //let (x, y) = dst;
-        let (tile_x, tile_y) = st.coords;
-        let (size_x, size_y) = st.size;
-        let src = Some(Rect::new(tile_x as i32, tile_y as i32, size_x as i32, size_y as i32));
-        let (dst_x, dst_y) = dst_coords;
-        let (dst_size_x, dst_size_y) = dst_size;
-        let dst = Some(Rect::new(dst_x as i32, dst_y as i32, dst_size_x as i32, dst_size_y as i32));
-        // FIXME this is lame
-        renderer.copy(&*self.texture, src, dst).is_ok()
+                     dst_coords: (int, int), dst_size: (uint, uint)) -> P2duxResult<()> {
+        let (src, dst) = tile_rects(st, dst_coords, dst_size);
+        self.copy(renderer, src, dst)
+    }
+
+    pub fn copy(&self, renderer: &Renderer, src: Rect, dst: Rect) -> P2duxResult<()> {
+        let texture = self.texture.borrow();
+        let texture = match *texture {
+            Some(ref texture) => texture,
+            None => return Err(P2duxError::RenderCall(
+                format!("draw_tile(): sheet '{}' is evicted", self.name)))
+        };
+        match renderer.copy(&**texture, Some(src), Some(dst)) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(P2duxError::RenderCall(
+                format!("draw_tile(): copy from sheet '{}' failed: {}", self.name, e)))
+        }
     }
 }
+
+fn load(renderer: &Renderer, path_str: &String) -> P2duxResult<(Box<Surface>, Box<Texture>)> {
+    let path = Path::new(path_str.as_slice());
+    let surface = match LoadSurface::from_file(&path) {
+        Ok(s) => s,
+        Err(msg) => return Err(P2duxError::ImageLoad(path_str.clone(), msg))
+    };
+    let surface = box surface;
+    let texture = match renderer.create_texture_from_surface(&*surface) {
+        Ok(t) => t,
+        Err(msg) => return Err(P2duxError::ImageLoad(path_str.clone(), msg))
+    };
+    Ok((surface, box texture))
+}
+
+fn load_bytes(renderer: &Renderer, bytes: &[u8],
+              path_str: &String) -> P2duxResult<(Box<Surface>, Box<Texture>)> {
+    let rwops = match RWops::from_bytes(bytes) {
+        Ok(r) => r,
+        Err(msg) => return Err(P2duxError::ImageLoad(path_str.clone(), msg))
+    };
+    let surface = match rwops.load() {
+        Ok(s) => s,
+        Err(msg) => return Err(P2duxError::ImageLoad(path_str.clone(), msg))
+    };
+    let surface = box surface;
+    let texture = match renderer.create_texture_from_surface(&*surface) {
+        Ok(t) => t,
+        Err(msg) => return Err(P2duxError::ImageLoad(path_str.clone(), msg))
+    };
+    Ok((surface, box texture))
+}
+
+fn surface_size(surface: &Surface) -> (uint, uint) {
+    (surface.get_width() as uint, surface.get_height() as uint)
+}
+
+// 0 when the file can't be stat'd
+fn modified_time(path_str: &String) -> u64 {
+    match fs::stat(&Path::new(path_str.as_slice())) {
+        Ok(stat) => stat.modified,
+        Err(_) => 0
+    }
+}
+
+// source rect on the sheet and destination rect on screen for a tile
+pub fn tile_rects(st: &SpriteTile, dst_coords: (int, int), dst_size: (uint, uint)) -> (Rect, Rect) {
+    let (tile_x, tile_y) = st.coords;
+    let (size_x, size_y) = st.size;
+    let src = Rect::new(tile_x as i32, tile_y as i32, size_x as i32, size_y as i32);
+    let (dst_x, dst_y) = dst_coords;
+    let (dst_size_x, dst_size_y) = dst_size;
+    let dst = Rect::new(dst_x as i32, dst_y as i32, dst_size_x as i32, dst_size_y as i32);
+    (src, dst)
+}
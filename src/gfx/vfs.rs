@@ -0,0 +1,256 @@
+// Copyright 2013-2014 Jeffery Olson
+//
+// Licensed under the 3-Clause BSD License, see LICENSE.txt
+// at the top-level of this repository.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::vec::Vec;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::io;
+use std::io::{File, BufReader, IoResult, IoError};
+use std::io::fs::PathExtensions;
+
+use error::{P2duxError, P2duxResult};
+
+// Where GameDisplay reads sheet pngs from once a vfs is set with
+// GameDisplay::set_vfs() (or given to its constructor); SpriteSheet paths
+// are handed to it as-is.
+pub trait Vfs {
+    fn read(&self, path: &str) -> P2duxResult<Vec<u8>>;
+    fn exists(&self, path: &str) -> bool;
+}
+
+// How a GameDisplay holds its vfs, so a SheetLoader's worker can share it
+pub type SharedVfs = Arc<Box<Vfs + Send + Sync + 'static>>;
+
+// Reads files from under `root` on disk
+pub struct DiskVfs {
+    root: Path
+}
+
+// Files held in memory: inserted one by one (e.g. from include_bin!) or
+// read out of a pack file
+pub struct MemoryVfs {
+    files: HashMap<String, Vec<u8>>
+}
+
+// Pack files are the magic bytes, an entry count and then, for each entry,
+// its name and its data, each preceded by a byte length. All the numbers
+// are little endian u32s.
+static PACK_MAGIC: &'static [u8] = b"P2DPACK1";
+
+impl DiskVfs {
+    pub fn new(root: Path) -> DiskVfs {
+        DiskVfs { root: root }
+    }
+}
+
+impl Vfs for DiskVfs {
+    fn read(&self, path: &str) -> P2duxResult<Vec<u8>> {
+        let full = self.root.join(path);
+        match File::open(&full).read_to_end() {
+            Ok(bytes) => Ok(bytes),
+            Err(e) => Err(P2duxError::Io(full.display().to_string(), e.to_string()))
+        }
+    }
+    fn exists(&self, path: &str) -> bool {
+        self.root.join(path).exists()
+    }
+}
+
+impl MemoryVfs {
+    pub fn new() -> MemoryVfs {
+        MemoryVfs { files: HashMap::new() }
+    }
+
+    pub fn insert(&mut self, path: &str, bytes: &[u8]) {
+        self.files.insert(path.to_string(), bytes.to_vec());
+    }
+
+    pub fn len(&self) -> uint { self.files.len() }
+
+    pub fn get_paths(&self) -> Vec<String> {
+        self.files.keys().map(|k| k.clone()).collect()
+    }
+
+    // Reads every entry of a pack file on disk into memory
+    pub fn open_pack(path: &Path) -> P2duxResult<MemoryVfs> {
+        let path_str = path.display().to_string();
+        match File::open(path).read_to_end() {
+            Ok(bytes) => MemoryVfs::from_pack(path_str.as_slice(), bytes.as_slice()),
+            Err(e) => Err(P2duxError::Io(path_str, e.to_string()))
+        }
+    }
+
+    // For a pack that's already in memory, e.g. include_bin!("sheets.pack");
+    // `source` only names the pack in errors
+    pub fn from_pack(source: &str, bytes: &[u8]) -> P2duxResult<MemoryVfs> {
+        match read_pack(bytes) {
+            Ok(Some(vfs)) => Ok(vfs),
+            Ok(None) => Err(P2duxError::Io(source.to_string(), "not a pack file".to_string())),
+            Err(e) => Err(P2duxError::Io(source.to_string(), e.to_string()))
+        }
+    }
+
+    // Writes every file out as a pack that open_pack()/from_pack() can read
+    pub fn save_pack(&self, path: &Path) -> P2duxResult<()> {
+        match write_pack(path, &self.files) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(P2duxError::Io(path.display().to_string(), e.to_string()))
+        }
+    }
+}
+
+impl Vfs for MemoryVfs {
+    fn read(&self, path: &str) -> P2duxResult<Vec<u8>> {
+        match self.files.get(&path.to_string()) {
+            Some(bytes) => Ok(bytes.clone()),
+            None => Err(P2duxError::Io(path.to_string(), "no such file in vfs".to_string()))
+        }
+    }
+    fn exists(&self, path: &str) -> bool {
+        self.files.contains_key(&path.to_string())
+    }
+}
+
+// Ok(None) when the magic bytes are wrong
+fn read_pack(bytes: &[u8]) -> IoResult<Option<MemoryVfs>> {
+    let mut reader = BufReader::new(bytes);
+    let magic = try!(reader.read_exact(PACK_MAGIC.len()));
+    if magic.as_slice() != PACK_MAGIC {
+        return Ok(None);
+    }
+    let mut vfs = MemoryVfs::new();
+    let count = try!(reader.read_le_u32());
+    for _ in range(0, count) {
+        let name = try!(read_entry_part(&mut reader, bytes.len()));
+        let name = String::from_utf8_lossy(name.as_slice()).into_string();
+        let data = try!(read_entry_part(&mut reader, bytes.len()));
+        vfs.files.insert(name, data);
+    }
+    Ok(Some(vfs))
+}
+
+// A length and that many bytes. The length is checked against what's left
+// of the pack first, so a corrupt one can't have us allocate gigabytes.
+fn read_entry_part(reader: &mut BufReader, total: uint) -> IoResult<Vec<u8>> {
+    let len = try!(reader.read_le_u32()) as uint;
+    let pos = try!(reader.tell()) as uint;
+    if len > total - pos {
+        return Err(IoError {
+            kind: io::EndOfFile,
+            desc: "pack entry runs past the end of the pack",
+            detail: Some(format!("{} bytes at offset {}, {} left", len, pos, total - pos))
+        });
+    }
+    reader.read_exact(len)
+}
+
+fn write_pack(path: &Path, files: &HashMap<String, Vec<u8>>) -> IoResult<()> {
+    let mut file = try!(File::create(path));
+    try!(file.write(PACK_MAGIC));
+    try!(file.write_le_u32(files.len() as u32));
+    for (name, data) in files.iter() {
+        try!(file.write_le_u32(name.len() as u32));
+        try!(file.write(name.as_bytes()));
+        try!(file.write_le_u32(data.len() as u32));
+        try!(file.write(data.as_slice()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{File, TempDir};
+    use super::{Vfs, MemoryVfs, DiskVfs};
+
+    fn sample() -> MemoryVfs {
+        let mut vfs = MemoryVfs::new();
+        vfs.insert("sheets/font.png", &[0x89, b'P', b'N', b'G', 0, 255, 13, 10]);
+        vfs.insert("sheets/ui.png", &[1, 2, 3]);
+        vfs.insert("empty", &[]);
+        vfs
+    }
+
+    fn assert_same(a: &MemoryVfs, b: &MemoryVfs) {
+        assert_eq!(a.len(), b.len());
+        for path in a.get_paths().iter() {
+            assert_eq!(a.read(path.as_slice()), b.read(path.as_slice()));
+        }
+    }
+
+    #[test]
+    fn memory_read_and_exists() {
+        let vfs = sample();
+        assert!(vfs.exists("sheets/ui.png"));
+        assert!(!vfs.exists("sheets/missing.png"));
+        assert_eq!(vfs.read("sheets/ui.png"), Ok(vec![1, 2, 3]));
+        assert_eq!(vfs.read("empty"), Ok(vec![]));
+        assert!(vfs.read("sheets/missing.png").is_err());
+    }
+
+    #[test]
+    fn pack_round_trip() {
+        let dir = TempDir::new("p2dux-vfs").unwrap();
+        let path = dir.path().join("test.pack");
+        let vfs = sample();
+        vfs.save_pack(&path).unwrap();
+        let loaded = MemoryVfs::open_pack(&path).unwrap();
+        assert_same(&vfs, &loaded);
+
+        let bytes = File::open(&path).read_to_end().unwrap();
+        let from_bytes = MemoryVfs::from_pack("test.pack", bytes.as_slice()).unwrap();
+        assert_same(&vfs, &from_bytes);
+    }
+
+    #[test]
+    fn empty_pack_round_trip() {
+        let dir = TempDir::new("p2dux-vfs").unwrap();
+        let path = dir.path().join("empty.pack");
+        MemoryVfs::new().save_pack(&path).unwrap();
+        assert_eq!(MemoryVfs::open_pack(&path).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn truncated_pack_is_an_error() {
+        let dir = TempDir::new("p2dux-vfs").unwrap();
+        let path = dir.path().join("test.pack");
+        sample().save_pack(&path).unwrap();
+        let bytes = File::open(&path).read_to_end().unwrap();
+        for len in range(0, bytes.len()) {
+            assert!(MemoryVfs::from_pack("test.pack", bytes.slice_to(len)).is_err());
+        }
+    }
+
+    #[test]
+    fn oversized_entry_is_an_error() {
+        let mut bytes = b"P2DPACK1".to_vec();
+        // one entry, named "a", claiming 4GB of data
+        bytes.push_all(&[1, 0, 0, 0, 1, 0, 0, 0, b'a', 0xff, 0xff, 0xff, 0xff, 1, 2, 3]);
+        assert!(MemoryVfs::from_pack("huge", bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn wrong_magic_is_an_error() {
+        assert!(MemoryVfs::from_pack("bogus", b"PK\x03\x04 not a pack").is_err());
+    }
+
+    #[test]
+    fn missing_pack_file_is_an_error() {
+        let dir = TempDir::new("p2dux-vfs").unwrap();
+        assert!(MemoryVfs::open_pack(&dir.path().join("nope.pack")).is_err());
+    }
+
+    #[test]
+    fn disk_reads_under_root() {
+        let dir = TempDir::new("p2dux-vfs").unwrap();
+        File::create(&dir.path().join("a.png")).write(&[7, 8, 9]).unwrap();
+        let vfs = DiskVfs::new(dir.path().clone());
+        assert!(vfs.exists("a.png"));
+        assert!(!vfs.exists("b.png"));
+        assert_eq!(vfs.read("a.png"), Ok(vec![7, 8, 9]));
+        assert!(vfs.read("b.png").is_err());
+    }
+}
@@ -0,0 +1,69 @@
+// Copyright 2013-2014 Jeffery Olson
+//
+// Licensed under the 3-Clause BSD License, see LICENSE.txt
+// at the top-level of this repository.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::vec::Vec;
+
+use sdl2::rect::Rect;
+
+use error::{P2duxError, P2duxResult};
+use super::GameDisplay;
+use super::camera::Camera;
+
+// A screen rectangle with its own camera. Between begin_viewport() and
+// end_viewport() all drawing is relative to the rect's top-left corner and
+// clipped to it, so the camera's viewport origin stays at (0, 0).
+pub struct Viewport {
+    pub rect: Rect,
+    pub camera: Camera
+}
+
+impl Viewport {
+    pub fn new(coords: (int, int), size: (uint, uint)) -> Viewport {
+        let (x, y) = coords;
+        let (w, h) = size;
+        Viewport {
+            rect: Rect::new(x as i32, y as i32, w as i32, h as i32),
+            camera: Camera::new(size)
+        }
+    }
+
+    // `count` equal viewports side by side across a screen of `screen_size`
+    pub fn split_screen(screen_size: (uint, uint), count: uint) -> Vec<Viewport> {
+        let (w, h) = screen_size;
+        let each = if count > 0 { w / count } else { w };
+        range(0, count).map(|i| Viewport::new(((i * each) as int, 0), (each, h))).collect()
+    }
+}
+
+impl GameDisplay {
+    pub fn begin_viewport(&self, viewport: &Viewport) -> P2duxResult<()> {
+        // anything batched so far belongs to whatever was active before
+        try!(self.flush());
+        match self.renderer.set_viewport(Some(viewport.rect)) {
+            Ok(()) => {},
+            Err(e) => return Err(P2duxError::RenderCall(format!("begin_viewport(): set_viewport(): {}", e)))
+        }
+        let clip = Rect::new(0, 0, viewport.rect.w, viewport.rect.h);
+        match self.renderer.set_clip_rect(Some(clip)) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(P2duxError::RenderCall(format!("begin_viewport(): set_clip_rect(): {}", e)))
+        }
+    }
+
+    // back to drawing on the whole screen
+    pub fn end_viewport(&self) -> P2duxResult<()> {
+        try!(self.flush());
+        match self.renderer.set_clip_rect(None) {
+            Ok(()) => {},
+            Err(e) => return Err(P2duxError::RenderCall(format!("end_viewport(): set_clip_rect(): {}", e)))
+        }
+        match self.renderer.set_viewport(None) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(P2duxError::RenderCall(format!("end_viewport(): set_viewport(): {}", e)))
+        }
+    }
+}
@@ -0,0 +1,109 @@
+// Copyright 2013-2014 Jeffery Olson
+//
+// Licensed under the 3-Clause BSD License, see LICENSE.txt
+// at the top-level of this repository.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::vec::Vec;
+
+use sdl2::event::{Event, WindowEventId};
+use sdl2::render::RendererParent;
+use sdl2::video;
+use sdl2::video::{Window, FullscreenType, DisplayMode};
+
+use error::{P2duxError, P2duxResult};
+use super::GameDisplay;
+
+#[deriving(Clone, PartialEq, Show, Encodable, Decodable)]
+pub enum WindowMode {
+    Windowed,
+    Fullscreen,
+    // fullscreen at the desktop's resolution, without a mode switch
+    Borderless
+}
+
+pub fn fullscreen_type(mode: WindowMode) -> FullscreenType {
+    match mode {
+        WindowMode::Windowed => FullscreenType::FTOff,
+        WindowMode::Fullscreen => FullscreenType::FTTrue,
+        WindowMode::Borderless => FullscreenType::FTDesktop
+    }
+}
+
+impl GameDisplay {
+    fn get_window<'a>(&'a self) -> P2duxResult<&'a Window> {
+        match *self.renderer.get_parent() {
+            RendererParent::Window(ref window) => Ok(window),
+            _ => Err(P2duxError::NoWindow)
+        }
+    }
+
+    pub fn set_window_mode(&self, mode: WindowMode) -> P2duxResult<()> {
+        let window = try!(self.get_window());
+        if !window.set_fullscreen(fullscreen_type(mode)) {
+            return Err(P2duxError::RenderCall(format!("set_window_mode(): couldn't switch to {}", mode)));
+        }
+        let size = self.get_window_size();
+        self.notify_resize(size);
+        Ok(())
+    }
+
+    pub fn resize(&self, size: (int, int)) -> P2duxResult<()> {
+        let window = try!(self.get_window());
+        let (w, h) = size;
+        window.set_size(w, h);
+        self.notify_resize(size);
+        Ok(())
+    }
+
+    // size of what's being drawn to, in pixels
+    pub fn get_window_size(&self) -> (int, int) {
+        match self.renderer.get_output_size() {
+            Ok(size) => size,
+            Err(_) => (0, 0)
+        }
+    }
+
+    // every mode the display the window is on supports
+    pub fn get_display_modes(&self) -> P2duxResult<Vec<DisplayMode>> {
+        let window = try!(self.get_window());
+        let display_index = match window.get_display_index() {
+            Ok(idx) => idx,
+            Err(e) => return Err(P2duxError::RenderCall(format!("get_display_modes(): {}", e)))
+        };
+        let count = match video::get_num_display_modes(display_index) {
+            Ok(n) => n,
+            Err(e) => return Err(P2duxError::RenderCall(format!("get_display_modes(): {}", e)))
+        };
+        let mut modes = Vec::new();
+        for i in range(0, count) {
+            match video::get_display_mode(display_index, i) {
+                Ok(mode) => modes.push(mode),
+                Err(e) => return Err(P2duxError::RenderCall(format!("get_display_modes(): {}", e)))
+            }
+        }
+        Ok(modes)
+    }
+
+    // Picks up resizes made by the user through the window manager
+    pub fn check_resize_event(&self, event: &Event) {
+        match *event {
+            Event::Window(_, _, WindowEventId::Resized, w, h) |
+            Event::Window(_, _, WindowEventId::SizeChanged, w, h) => self.notify_resize((w, h)),
+            _ => {}
+        }
+    }
+
+    pub fn notify_resize(&self, size: (int, int)) {
+        self.pending_resize.set(Some(size));
+    }
+
+    // The new size if the window has been resized since the last call.
+    // View::enter() calls this every frame and passes it on to on_resize().
+    pub fn take_resize(&self) -> Option<(int, int)> {
+        let size = self.pending_resize.get();
+        self.pending_resize.set(None);
+        size
+    }
+}
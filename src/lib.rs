@@ -18,35 +18,101 @@ extern crate collections;
 extern crate p2d;
 extern crate sdl2;
 extern crate sdl2_image;
+use std::rc::Rc;
+use std::cell::Cell;
+use std::collections::RingBuf;
+use std::io::timer;
+use std::time::duration::Duration;
 use time::precise_time_ns;
 
+pub mod error;
 pub mod gfx;
 pub mod ui;
 pub mod view;
 
+// Source of millisecond timestamps for the view loop. Swapping in a
+// ManualClock lets tests control exactly what time views see.
+pub trait Clock {
+    fn now_ms(&self) -> u64;
+    fn sleep_ms(&self, ms: u64);
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_ms(&self) -> u64 { precise_time_ns() / 1000000u64 }
+    fn sleep_ms(&self, ms: u64) { timer::sleep(Duration::milliseconds(ms as i64)); }
+}
+
+// Clones share the same underlying time, so a test can keep a handle
+// while the ViewContext owns another.
+#[deriving(Clone)]
+pub struct ManualClock {
+    now: Rc<Cell<u64>>
+}
+
+impl ManualClock {
+    pub fn new(start_ms: u64) -> ManualClock {
+        ManualClock { now: Rc::new(Cell::new(start_ms)) }
+    }
+    pub fn set(&self, ms: u64) { self.now.set(ms); }
+    pub fn advance(&self, ms: u64) { self.now.set(self.now.get() + ms); }
+}
+
+impl Clock for ManualClock {
+    fn now_ms(&self) -> u64 { self.now.get() }
+    // nobody actually waits; time just jumps ahead
+    fn sleep_ms(&self, ms: u64) { self.advance(ms); }
+}
+
+// lets one clock be read from several places, see ViewContext::get_clock()
+impl Clock for Rc<Box<Clock + 'static>> {
+    fn now_ms(&self) -> u64 { (**self).now_ms() }
+    fn sleep_ms(&self, ms: u64) { (**self).sleep_ms(ms) }
+}
+
+// how many recent frame times TimeTracker::new() keeps around
+pub static DEFAULT_FRAME_HISTORY: uint = 120;
+// weight of the newest frame in the smoothed frame time
+static SMOOTHING_FACTOR: f64 = 0.1;
+
 pub struct TimeTracker {
     pub last_time: u64,
     pub now_time: u64,
     pub next_fps_time: u64,
     pub fps_ctr: uint,
-    pub curr_fps: uint
+    pub curr_fps: uint,
+    clock: Box<Clock + 'static>,
+    frame_times: RingBuf<u64>,
+    history_len: uint,
+    smoothed_frame_time: f64,
+    primed: bool
 }
 impl TimeTracker {
     pub fn new() -> TimeTracker {
-        let curr_time = precise_time_ns() / 1000000u64;
+        TimeTracker::with_clock(box SystemClock, DEFAULT_FRAME_HISTORY)
+    }
+    // keeps the last `history_len` frame times, reading time from `clock`
+    pub fn with_clock(clock: Box<Clock + 'static>, history_len: uint) -> TimeTracker {
+        let curr_time = clock.now_ms();
         let mut tt = TimeTracker {
             last_time: curr_time,
             now_time: curr_time,
             next_fps_time: curr_time + 1000u64,
             fps_ctr: 0,
-            curr_fps: 0
+            curr_fps: 0,
+            clock: clock,
+            frame_times: RingBuf::with_capacity(history_len),
+            history_len: history_len,
+            smoothed_frame_time: 0.0,
+            primed: false
         };
         tt.update();
         tt
     }
     pub fn update(&mut self) {
         self.last_time = self.now_time;
-        self.now_time = precise_time_ns() / 1000000u64;
+        self.now_time = self.clock.now_ms();
         if self.now_time >= self.next_fps_time {
             self.curr_fps = self.fps_ctr;
             self.fps_ctr = 0;
@@ -54,7 +120,144 @@ impl TimeTracker {
         } else {
             self.fps_ctr += 1;
         }
+        // the update() from the constructor isn't a real frame
+        if !self.primed {
+            self.primed = true;
+            return;
+        }
+        self.record_frame_time(self.now_time - self.last_time);
+    }
+    fn record_frame_time(&mut self, frame_time: u64) {
+        if self.history_len == 0 {
+            return;
+        }
+        if self.frame_times.len() == self.history_len {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+        if self.frame_times.len() == 1 {
+            self.smoothed_frame_time = frame_time as f64;
+        } else {
+            self.smoothed_frame_time +=
+                SMOOTHING_FACTOR * (frame_time as f64 - self.smoothed_frame_time);
+        }
     }
     pub fn get_curr_fps(&self) -> uint { self.curr_fps }
     pub fn get_ms_since(&self) -> uint { (self.now_time - self.last_time) as uint }
+
+    // recent frame times in ms, oldest first
+    pub fn get_frame_times(&self) -> Vec<u64> {
+        self.frame_times.iter().map(|t| *t).collect()
+    }
+    pub fn get_min_frame_time(&self) -> u64 {
+        self.frame_times.iter().map(|t| *t).min().unwrap_or(0)
+    }
+    pub fn get_max_frame_time(&self) -> u64 {
+        self.frame_times.iter().map(|t| *t).max().unwrap_or(0)
+    }
+    pub fn get_mean_frame_time(&self) -> f64 {
+        if self.frame_times.is_empty() {
+            return 0.0;
+        }
+        let total = self.frame_times.iter().fold(0u64, |acc, t| acc + *t);
+        total as f64 / self.frame_times.len() as f64
+    }
+    // nearest-rank percentile, `pct` from 0.0 to 100.0
+    pub fn get_percentile_frame_time(&self, pct: f64) -> u64 {
+        if self.frame_times.is_empty() {
+            return 0;
+        }
+        let mut sorted = self.get_frame_times();
+        sorted.sort();
+        let pct = pct.max(0.0).min(100.0);
+        let rank = (pct / 100.0 * sorted.len() as f64).ceil() as uint;
+        let idx = if rank == 0 { 0 } else { rank - 1 };
+        sorted[idx]
+    }
+    // number of recent frames that took more than `factor` times the mean
+    pub fn get_spike_count(&self, factor: f64) -> uint {
+        let threshold = self.get_mean_frame_time() * factor;
+        self.frame_times.iter().filter(|t| **t as f64 > threshold).count()
+    }
+    // fps derived from an exponential moving average of the frame time, so
+    // it updates every frame without jittering like the per-frame value
+    pub fn get_smoothed_fps(&self) -> f64 {
+        if self.smoothed_frame_time > 0.0 {
+            1000.0 / self.smoothed_frame_time
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TimeTracker, ManualClock};
+
+    // a tracker that has seen frames of the given lengths, in order
+    fn tracker_with(frame_times: &[u64], history_len: uint) -> TimeTracker {
+        let clock = ManualClock::new(1000);
+        let mut tt = TimeTracker::with_clock(box clock.clone(), history_len);
+        for ft in frame_times.iter() {
+            clock.advance(*ft);
+            tt.update();
+        }
+        tt
+    }
+
+    #[test]
+    fn empty_history() {
+        let tt = tracker_with(&[], 10);
+        assert!(tt.get_frame_times().is_empty());
+        assert_eq!(tt.get_min_frame_time(), 0);
+        assert_eq!(tt.get_max_frame_time(), 0);
+        assert_eq!(tt.get_mean_frame_time(), 0.0);
+        assert_eq!(tt.get_percentile_frame_time(50.0), 0);
+        assert_eq!(tt.get_spike_count(2.0), 0);
+        assert_eq!(tt.get_smoothed_fps(), 0.0);
+    }
+
+    #[test]
+    fn min_max_mean() {
+        let tt = tracker_with(&[30, 10, 100, 40, 20], 10);
+        assert_eq!(tt.get_frame_times(), vec![30, 10, 100, 40, 20]);
+        assert_eq!(tt.get_min_frame_time(), 10);
+        assert_eq!(tt.get_max_frame_time(), 100);
+        assert_eq!(tt.get_mean_frame_time(), 40.0);
+    }
+
+    #[test]
+    fn percentiles() {
+        let tt = tracker_with(&[30, 10, 100, 40, 20], 10);
+        assert_eq!(tt.get_percentile_frame_time(0.0), 10);
+        assert_eq!(tt.get_percentile_frame_time(20.0), 10);
+        assert_eq!(tt.get_percentile_frame_time(50.0), 30);
+        assert_eq!(tt.get_percentile_frame_time(90.0), 100);
+        assert_eq!(tt.get_percentile_frame_time(100.0), 100);
+        // out of range is clamped
+        assert_eq!(tt.get_percentile_frame_time(150.0), 100);
+    }
+
+    #[test]
+    fn spikes() {
+        let tt = tracker_with(&[30, 10, 100, 40, 20], 10);
+        // mean is 40; equal to the threshold isn't a spike
+        assert_eq!(tt.get_spike_count(1.0), 1);
+        assert_eq!(tt.get_spike_count(0.5), 3);
+        assert_eq!(tt.get_spike_count(2.5), 0);
+    }
+
+    #[test]
+    fn history_keeps_most_recent() {
+        let tt = tracker_with(&[30, 10, 100, 40, 20], 3);
+        assert_eq!(tt.get_frame_times(), vec![100, 40, 20]);
+        assert_eq!(tt.get_min_frame_time(), 20);
+        assert_eq!(tt.get_max_frame_time(), 100);
+    }
+
+    #[test]
+    fn smoothed_fps_of_steady_frames() {
+        let tt = tracker_with(&[20, 20, 20, 20], 10);
+        assert_eq!(tt.get_smoothed_fps(), 50.0);
+    }
 }
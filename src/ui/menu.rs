@@ -8,6 +8,7 @@
 use std::vec::Vec;
 
 use gfx::GameDisplay;
+use error::P2duxResult;
 
 use super::{UiBox, UiFont, draw_text_box, compute_text_box_bounds};
 
@@ -70,21 +71,23 @@ impl<TFont: UiFont, TBox: UiBox>
         };
         format!("{} {}", *prefix, entry)
     }
-    pub fn update_bounds(&mut self, coords: (int, int), ui_font: &TFont, ui_box: &TBox) {
+    pub fn update_bounds(&mut self, coords: (int, int), ui_font: &TFont, ui_box: &TBox) -> P2duxResult<()> {
         // figure out width, in pixels, of the text (based on longest entry line)
         self.formatted_entries = Vec::new();
         for v in range(0, self.entries.len()) {
             let formatted = self.get_formatted(v);
             self.formatted_entries.push(formatted);
         }
-        self.box_size = compute_text_box_bounds(
-            self.formatted_entries.as_slice(), ui_font, ui_box, self.text_gap);
+        self.box_size = try!(compute_text_box_bounds(
+            self.formatted_entries.as_slice(), ui_font, ui_box, self.text_gap));
         self.coords = coords;
+        Ok(())
     }
 
-    pub fn draw_menu(&self, display: &GameDisplay, ui_font: &TFont, ui_box: &TBox) {
-        draw_text_box(
+    pub fn draw_menu(&self, display: &GameDisplay, ui_font: &TFont, ui_box: &TBox) -> P2duxResult<()> {
+        try!(draw_text_box(
             display, self.coords, self.box_size, self.bg_color,
-            self.formatted_entries.slice_from(0), ui_font, ui_box, self.text_gap);
+            self.formatted_entries.slice_from(0), ui_font, ui_box, self.text_gap));
+        Ok(())
     }
 }
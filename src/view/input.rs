@@ -0,0 +1,81 @@
+// Copyright 2013-2014 Jeffery Olson
+//
+// Licensed under the 3-Clause BSD License, see LICENSE.txt
+// at the top-level of this repository.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::vec::Vec;
+use std::collections::RingBuf;
+
+use sdl2::event::poll_event;
+use sdl2::event::Event;
+
+use ManualClock;
+use error::{P2duxError, P2duxResult};
+
+pub trait InputSource {
+    // push every event that arrived since the last poll onto `events`
+    fn poll_events(&mut self, events: &mut Vec<Event>) -> P2duxResult<()>;
+}
+
+pub struct SdlInput;
+
+impl InputSource for SdlInput {
+    fn poll_events(&mut self, events: &mut Vec<Event>) -> P2duxResult<()> {
+        loop {
+            match poll_event() {
+                Event::None => { break; },
+                event => { events.push(event); }
+            }
+        }
+        Ok(())
+    }
+}
+
+// One frame worth of scripted input, delivered at `time` (in ms)
+pub struct ScriptedFrame {
+    pub time: u64,
+    pub events: Vec<Event>
+}
+
+impl ScriptedFrame {
+    pub fn new(time: u64, events: Vec<Event>) -> ScriptedFrame {
+        ScriptedFrame { time: time, events: events }
+    }
+}
+
+// Feeds one ScriptedFrame per poll, moving the shared clock to that frame's
+// time. Once the script runs out, polling fails with InputExhausted so a
+// view that never returns doesn't hang the test.
+pub struct ScriptedInput {
+    frames: RingBuf<ScriptedFrame>,
+    clock: ManualClock
+}
+
+impl ScriptedInput {
+    pub fn new(frames: Vec<ScriptedFrame>, clock: ManualClock) -> ScriptedInput {
+        let mut queue = RingBuf::new();
+        for frame in frames.into_iter() {
+            queue.push_back(frame);
+        }
+        ScriptedInput { frames: queue, clock: clock }
+    }
+
+    pub fn remaining(&self) -> uint { self.frames.len() }
+}
+
+impl InputSource for ScriptedInput {
+    fn poll_events(&mut self, events: &mut Vec<Event>) -> P2duxResult<()> {
+        match self.frames.pop_front() {
+            Some(frame) => {
+                self.clock.set(frame.time);
+                for event in frame.events.into_iter() {
+                    events.push(event);
+                }
+                Ok(())
+            },
+            None => Err(P2duxError::InputExhausted)
+        }
+    }
+}
@@ -4,86 +4,287 @@
 // at the top-level of this repository.
 // This file may not be copied, modified, or distributed
 // except according to those terms.
-use std::io::timer;
-use std::any::Any;
 use std::comm::channel;
 use std::mem::{transmute, transmute_copy};
-use time::precise_time_ns;
-use std::time::duration::Duration;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::io;
+use std::io::fs;
+use std::io::fs::PathExtensions;
+use time;
 
-use sdl2::event::poll_event;
 use sdl2::event::Event;
+use sdl2::keycode::KeyCode;
 
+use {Clock, SystemClock, ManualClock};
 use gfx::GameDisplay;
+use error::{P2duxError, P2duxResult};
+use self::input::{InputSource, SdlInput, ScriptedInput, ScriptedFrame};
 
+pub mod input;
 pub mod prefab;
+pub mod stack;
 
 // View
 
+// How View::enter paces its loop. With a fixed_step_ms, my_fixed_update is
+// called once per elapsed step (at most max_steps_per_frame times a frame,
+// any backlog beyond that is dropped) and views can read the leftover
+// fraction of a step via ViewContext::get_interpolation_alpha(). Vsync is
+// a property of the renderer, see DisplayConfig::vsync().
+#[deriving(Clone, Show)]
+pub struct LoopPolicy {
+    pub target_fps: Option<uint>,
+    pub fixed_step_ms: Option<u64>,
+    pub max_steps_per_frame: uint
+}
+
+impl LoopPolicy {
+    // uncapped, no fixed step; what enter() has always done
+    pub fn new() -> LoopPolicy {
+        LoopPolicy {
+            target_fps: None,
+            fixed_step_ms: None,
+            max_steps_per_frame: 5
+        }
+    }
+}
+
 pub struct ViewContext {
-    display: GameDisplay
+    display: GameDisplay,
+    input: RefCell<Box<InputSource + 'static>>,
+    clock: Rc<Box<Clock + 'static>>,
+    loop_policy: LoopPolicy,
+    frame_delta: Cell<u64>,
+    frame_alpha: Cell<f64>,
+    screenshot_key: Option<KeyCode>,
+    screenshot_dir: Path,
+    screenshot_error: RefCell<Option<P2duxError>>
 }
 
 impl ViewContext {
     pub fn new(display: GameDisplay) -> ViewContext {
-        ViewContext { display: display }
+        ViewContext::with_sources(display, box SdlInput, box SystemClock)
+    }
+
+    pub fn with_sources(display: GameDisplay, input: Box<InputSource + 'static>,
+                        clock: Box<Clock + 'static>) -> ViewContext {
+        ViewContext {
+            display: display,
+            input: RefCell::new(input),
+            clock: Rc::new(clock),
+            loop_policy: LoopPolicy::new(),
+            frame_delta: Cell::new(0),
+            frame_alpha: Cell::new(1.0),
+            screenshot_key: None,
+            screenshot_dir: Path::new("screenshots"),
+            screenshot_error: RefCell::new(None)
+        }
+    }
+
+    // Drives views from a fixed script instead of SDL: each frame's events
+    // are delivered on one pass through the loop, and the clock reads that
+    // frame's time.
+    pub fn new_scripted(display: GameDisplay, frames: Vec<ScriptedFrame>) -> ViewContext {
+        let clock = ManualClock::new(0);
+        let input = ScriptedInput::new(frames, clock.clone());
+        ViewContext::with_sources(display, box input, box clock)
     }
     
     pub fn get_display<'a>(&'a self) -> &'a GameDisplay {
         &self.display
     }
+
+    pub fn get_time(&self) -> u64 {
+        self.clock.now_ms()
+    }
+
+    pub fn sleep_ms(&self, ms: u64) {
+        self.clock.sleep_ms(ms);
+    }
+
+    // a handle on the clock the loop runs by, e.g. for a TimeTracker that
+    // should see scripted time under new_scripted()
+    pub fn get_clock(&self) -> Box<Clock + 'static> {
+        box self.clock.clone()
+    }
+
+    pub fn poll_events(&self, events: &mut Vec<Event>) -> P2duxResult<()> {
+        self.input.borrow_mut().poll_events(events)
+    }
+
+    pub fn get_loop_policy<'a>(&'a self) -> &'a LoopPolicy {
+        &self.loop_policy
+    }
+
+    pub fn set_loop_policy(&mut self, policy: LoopPolicy) {
+        self.loop_policy = policy;
+    }
+
+    // ms elapsed since the previous frame of the running enter() loop
+    pub fn get_frame_delta(&self) -> u64 { self.frame_delta.get() }
+
+    // how far, from 0.0 to 1.0, the current frame is between the last fixed
+    // update and the next one; always 1.0 without a fixed step
+    pub fn get_interpolation_alpha(&self) -> f64 { self.frame_alpha.get() }
+
+    // While a key is set, pressing it during View::enter() saves the frame
+    // being drawn to a timestamped png in `dir`. A hotkey screenshot that
+    // fails doesn't stop the loop; see take_screenshot_error().
+    pub fn set_screenshot_hotkey(&mut self, key: Option<KeyCode>, dir: Path) {
+        self.screenshot_key = key;
+        self.screenshot_dir = dir;
+    }
+
+    // Saves the current back buffer to the screenshot directory, returning
+    // the path of the new png
+    pub fn take_screenshot(&self) -> P2duxResult<Path> {
+        if !self.screenshot_dir.exists() {
+            match fs::mkdir_recursive(&self.screenshot_dir, io::USER_RWX) {
+                Ok(()) => {},
+                Err(e) => return Err(P2duxError::Io(
+                    self.screenshot_dir.display().to_string(), e.to_string()))
+            }
+        }
+        let tm = time::now();
+        let name = format!("screenshot-{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}.png",
+                           tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday,
+                           tm.tm_hour, tm.tm_min, tm.tm_sec, tm.tm_nsec / 1000000);
+        let path = self.screenshot_dir.join(name);
+        try!(self.display.screenshot(&path));
+        Ok(path)
+    }
+
+    // the error from the most recent failed hotkey screenshot, if any,
+    // clearing it
+    pub fn take_screenshot_error(&self) -> Option<P2duxError> {
+        self.screenshot_error.borrow_mut().take()
+    }
+
+    fn is_screenshot_requested(&self, events: &[Event]) -> bool {
+        let key = match self.screenshot_key {
+            Some(key) => key,
+            None => return false
+        };
+        events.iter().any(|event| match *event {
+            Event::KeyDown(_, _, k, _, _, _) => k == key,
+            _ => false
+        })
+    }
+
+    fn set_frame_timing(&self, delta: u64, alpha: f64) {
+        self.frame_delta.set(delta);
+        self.frame_alpha.set(alpha);
+    }
 }
 
-impl<'a, TView: View> View for &'a mut TView {
-    fn get_parent<'a>(&'a mut self) -> Option<&'a mut View> {
+impl<'a, TView: PassiveView> PassiveView for &'a mut TView {
+    fn my_passive(&mut self, ctx: &ViewContext, time: u64) -> P2duxResult<()> {
+        (*self).my_passive(ctx, time)
+    }
+    fn on_resize(&mut self, ctx: &ViewContext, size: (int, int)) -> P2duxResult<()> {
+        (*self).on_resize(ctx, size)
+    }
+}
+
+impl<'a, TOut, TView: View<TOut>> View<TOut> for &'a mut TView {
+    fn get_parent<'a>(&'a mut self) -> Option<&'a mut PassiveView> {
         (*self).get_parent()
     }
-    fn my_active(&mut self, ctx: &ViewContext, events: &[Event], time: u64) -> Option<Box<Any>> {
+    fn my_active(&mut self, ctx: &ViewContext, events: &[Event], time: u64) -> P2duxResult<Option<TOut>> {
         (*self).my_active(ctx, events, time)
     }
-    fn my_passive(&mut self, ctx: &ViewContext, time: u64) {
-        (*self).my_passive(ctx, time);
+    fn my_fixed_update(&mut self, ctx: &ViewContext, step_ms: u64) -> P2duxResult<()> {
+        (*self).my_fixed_update(ctx, step_ms)
     }
 }
 
-pub trait View {
-    fn get_parent<'a>(&'a mut self) -> Option<&'a mut View>;
-    fn my_active<'a>(&'a mut self, ctx: &ViewContext, events: &[Event], time: u64) -> Option<Box<Any>>;
-    fn my_passive(& mut self, ctx: &ViewContext, time: u64);
-    fn parent_passive(&mut self, ctx: &ViewContext, time: u64) {
+// The drawing half of a view; this is all a parent needs to provide, so
+// views with different output types can be stacked on top of one another.
+pub trait PassiveView {
+    fn my_passive(& mut self, ctx: &ViewContext, time: u64) -> P2duxResult<()>;
+    // the window changed size (to `size`, in pixels); a chance to re-layout,
+    // e.g. VertTextMenu::update_bounds() to re-center a menu
+    fn on_resize(&mut self, _ctx: &ViewContext, _size: (int, int)) -> P2duxResult<()> {
+        Ok(())
+    }
+}
+
+// A view that runs until my_active produces a TOut, which enter() returns.
+pub trait View<TOut>: PassiveView {
+    fn get_parent<'a>(&'a mut self) -> Option<&'a mut PassiveView>;
+    fn my_active<'a>(&'a mut self, ctx: &ViewContext, events: &[Event], time: u64) -> P2duxResult<Option<TOut>>;
+    // called at the loop policy's fixed rate, ahead of the frame's passive
+    // pass; does nothing unless fixed_step_ms is set
+    fn my_fixed_update(&mut self, _ctx: &ViewContext, _step_ms: u64) -> P2duxResult<()> {
+        Ok(())
+    }
+    fn parent_passive(&mut self, ctx: &ViewContext, time: u64) -> P2duxResult<()> {
         match self.get_parent() {
             Some(parent) => parent.my_passive(ctx, time),
-            None => {}
+            None => Ok(())
         }
     }
-    fn enter(&mut self, ctx: &ViewContext) -> Box<Any> {
-        let mut cont = true;
+    fn enter(&mut self, ctx: &ViewContext) -> P2duxResult<TOut> {
+        let policy = ctx.get_loop_policy().clone();
         let mut events = Vec::new();
-        let mut output: Option<Box<Any>> = None;
-        while cont {
-            let result = {
-                let time = precise_time_ns() / 1000000;
-                self.my_passive(ctx, time);
-                loop {
-                    match poll_event() {
-                        Event::None => { break; },
-                        event => { events.push(event); }
+        let mut last_time = ctx.get_time();
+        let mut accumulated = 0u64;
+        loop {
+            let frame_start = ctx.get_time();
+            let delta = if frame_start > last_time { frame_start - last_time } else { 0 };
+            last_time = frame_start;
+            let alpha = match policy.fixed_step_ms {
+                Some(step) if step > 0 => {
+                    accumulated += delta;
+                    let mut steps = 0;
+                    while accumulated >= step && steps < policy.max_steps_per_frame {
+                        try!(self.my_fixed_update(ctx, step));
+                        accumulated -= step;
+                        steps += 1;
                     }
-                }
-                let time = precise_time_ns() / 1000000;
-                let result = self.my_active(ctx, events.as_slice(), time);
-                ctx.get_display().renderer.present();
-                events.clear();
-                result
+                    accumulated = accumulated % step;
+                    accumulated as f64 / step as f64
+                },
+                _ => 1.0
             };
-            match result {
-                None => {},
-                r => {
-                    cont = false;
-                    output = r;
+            ctx.set_frame_timing(delta, alpha);
+
+            try!(self.my_passive(ctx, frame_start));
+            try!(ctx.poll_events(&mut events));
+            for event in events.iter() {
+                ctx.get_display().check_resize_event(event);
+            }
+            match ctx.get_display().take_resize() {
+                Some(size) => try!(self.on_resize(ctx, size)),
+                None => {}
+            }
+            let time = ctx.get_time();
+            let result = try!(self.my_active(ctx, events.as_slice(), time));
+            if ctx.is_screenshot_requested(events.as_slice()) {
+                match ctx.take_screenshot() {
+                    Ok(_) => {},
+                    Err(e) => *ctx.screenshot_error.borrow_mut() = Some(e)
                 }
             }
+            try!(ctx.get_display().present());
+            events.clear();
+            match result {
+                Some(output) => return Ok(output),
+                None => {}
+            }
+
+            match policy.target_fps {
+                Some(fps) if fps > 0 => {
+                    let frame_ms = 1000 / fps as u64;
+                    let now = ctx.get_time();
+                    let spent = if now > frame_start { now - frame_start } else { 0 };
+                    if spent < frame_ms {
+                        ctx.sleep_ms(frame_ms - spent);
+                    }
+                },
+                _ => {}
+            }
         }
-        output.expect("View.enter(): Should always be a Some here")
     }
 }
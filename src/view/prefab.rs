@@ -4,17 +4,30 @@
 // at the top-level of this repository.
 // This file may not be copied, modified, or distributed
 // except according to those terms.
-use std::any::Any;
 use std::mem::transmute_copy;
+use std::cmp::{max, min};
 use sdl2::event::Event;
 use sdl2::event::Event::{TextInput, TextEditing, KeyDown};
 use sdl2::keyboard::{start_text_input, stop_text_input, is_text_input_active};
 use sdl2::keycode::*;
+use sdl2::rect::Rect;
+use sdl2::pixels::Color;
 
+use {TimeTracker, DEFAULT_FRAME_HISTORY};
+
+use gfx::loader::SheetLoader;
+use gfx::texture::SheetHandle;
 use ui::{compute_text_box_bounds, draw_text_box};
+use error::{P2duxError, P2duxResult};
 
 use super::super::ui::{UiFont, UiBox};
-use super::{View, ViewContext};
+use super::{View, PassiveView, ViewContext};
+
+#[deriving(Clone, PartialEq, Show)]
+pub enum TextInputResult {
+    Cancelled,
+    Submitted(String)
+}
 
 pub struct TextInputDialogView<'a, TFont:'a, TBox:'a, TParent: 'a> {
     input_state: String,
@@ -32,11 +45,48 @@ pub struct TextInputDialogView<'a, TFont:'a, TBox:'a, TParent: 'a> {
     parent: &'a mut TParent
 }
 
+// Wraps another view, passing everything through to it, and draws frame
+// statistics over the top of it in a text box at `coords`: fps, frame time,
+// draw_tile calls and loaded sheet count for the last frame, with a strip
+// of recent frame times as bars beneath. Pressing `toggle_key` shows or
+// hides it. Frame times come from the ViewContext's clock.
+//
+// The font needs glyphs for A-Z, 0-9 and space. If the overlay can't be
+// drawn it's left off for that frame; it never fails the wrapped view.
+pub struct PerfOverlayView<'a, TFont:'a, TBox:'a, TParent: 'a> {
+    // made on the first frame, from the context's clock
+    tracker: Option<TimeTracker>,
+    toggle_key: KeyCode,
+    visible: bool,
+    bg_color: (u8, u8, u8),
+    coords: (int, int),
+    text_gap: uint,
+    graph_height: uint,
+    ui_font: &'a TFont,
+    ui_box: &'a TBox,
+    parent: &'a mut TParent
+}
+
+// Clears the screen and draws a progress bar inside a UiBox at `coords`
+// while a SheetLoader finishes, uploading at most `uploads_per_frame`
+// sheets a frame so the bar keeps moving; enter() returns the handles
+// of every sheet once they're all loaded. Any that failed to load are
+// listed by get_failed() afterwards.
+pub struct LoadingScreenView<'a, TBox:'a> {
+    loader: SheetLoader,
+    uploads_per_frame: uint,
+    bg_color: (u8, u8, u8),
+    bar_color: (u8, u8, u8),
+    coords: (int, int),
+    box_size: (uint, uint),
+    ui_box: &'a TBox
+}
+
 pub struct DisplayClearerView {
     bg_color: (u8, u8, u8)
 }
 
-impl<'a, TFont: UiFont, TBox: UiBox, TParent: View> TextInputDialogView<'a, TFont, TBox, TParent> {
+impl<'a, TFont: UiFont, TBox: UiBox, TParent: PassiveView> TextInputDialogView<'a, TFont, TBox, TParent> {
     pub fn new(
         ui_font: &'a TFont,
         ui_box: &'a TBox,
@@ -48,7 +98,7 @@ impl<'a, TFont: UiFont, TBox: UiBox, TParent: View> TextInputDialogView<'a, TFon
         coords: (int, int),
         text_gap: uint,
         parent: &'a mut TParent)
-            -> TextInputDialogView<'a, TFont, TBox, TParent> {
+            -> P2duxResult<TextInputDialogView<'a, TFont, TBox, TParent>> {
         let mut bc = Vec::new();
         bc.push_all(preface);
         bc.push("".to_string());
@@ -68,11 +118,11 @@ impl<'a, TFont: UiFont, TBox: UiBox, TParent: View> TextInputDialogView<'a, TFon
             box_content: bc,
             parent: parent
         };
-        ret.update_content();
-        ret
+        try!(ret.update_content());
+        Ok(ret)
     }
     
-    fn update_content(&mut self) {
+    fn update_content(&mut self) -> P2duxResult<()> {
         self.box_content.pop();
         self.box_content.push("".to_string());
         let bc_last = self.box_content.len() - 1;
@@ -81,19 +131,27 @@ impl<'a, TFont: UiFont, TBox: UiBox, TParent: View> TextInputDialogView<'a, TFon
         let is_last = self.input_state.len();
         self.box_content[bc_last].push_str(self.input_state.slice(0, is_last));
         self.box_content[bc_last].push_str(self.cursor.as_slice());
-        self.box_size = compute_text_box_bounds(self.box_content.as_slice(), self.ui_font, self.ui_box, self.text_gap);
+        self.box_size = try!(compute_text_box_bounds(self.box_content.as_slice(), self.ui_font, self.ui_box, self.text_gap));
+        Ok(())
     }
 }
 
-impl<'a, TFont: UiFont, TBox: UiBox, TParent: View>
-        View for TextInputDialogView<'a, TFont, TBox, TParent> {
-    fn get_parent(&mut self) -> Option<&mut View> {
-        Some(&mut *self.parent as &mut View)
+impl<'a, TFont: UiFont, TBox: UiBox, TParent: PassiveView>
+        PassiveView for TextInputDialogView<'a, TFont, TBox, TParent> {
+    fn my_passive(&mut self, _ctx: &ViewContext, _time: u64) -> P2duxResult<()> { Ok(()) }
+    fn on_resize(&mut self, ctx: &ViewContext, size: (int, int)) -> P2duxResult<()> {
+        self.parent.on_resize(ctx, size)
     }
-    fn my_passive(&mut self, _ctx: &ViewContext, _time: u64) {}
-    fn my_active(&mut self, ctx: &ViewContext, events: &[Event], time: u64) -> Option<Box<Any>> {
+}
+
+impl<'a, TFont: UiFont, TBox: UiBox, TParent: PassiveView>
+        View<TextInputResult> for TextInputDialogView<'a, TFont, TBox, TParent> {
+    fn get_parent(&mut self) -> Option<&mut PassiveView> {
+        Some(&mut *self.parent as &mut PassiveView)
+    }
+    fn my_active(&mut self, ctx: &ViewContext, events: &[Event], time: u64) -> P2duxResult<Option<TextInputResult>> {
         // call into parent's passive
-        self.parent_passive(ctx, time);
+        try!(self.parent_passive(ctx, time));
 
         if !self.started {
             self.started = true;
@@ -106,7 +164,7 @@ impl<'a, TFont: UiFont, TBox: UiBox, TParent: View>
                 KeyDown(_, _, key, _, _, _) =>
                     match key {
                         KeyCode::Return => {
-                            out = Some((box self.input_state.clone()) as Box<Any>);
+                            out = Some(TextInputResult::Submitted(self.input_state.clone()));
                             stop_text_input();
                             break;
                         },
@@ -114,8 +172,7 @@ impl<'a, TFont: UiFont, TBox: UiBox, TParent: View>
                             self.input_state.pop();
                         }
                         KeyCode::Escape => {
-                            let outval = (box "".to_string()) as Box<Any>;
-                            out = Some(outval);
+                            out = Some(TextInputResult::Cancelled);
                             stop_text_input();
                             break;
                         },
@@ -129,11 +186,194 @@ impl<'a, TFont: UiFont, TBox: UiBox, TParent: View>
             }
         }
         if self.previous_state != self.input_state {
-            self.update_content();
+            try!(self.update_content());
+        }
+        try!(draw_text_box(ctx.get_display(), self.coords, self.box_size, self.bg_color, self.box_content.as_slice(), self.ui_font,
+                           self.ui_box, self.text_gap));
+        Ok(out)
+    }
+}
+
+impl<'a, TFont: UiFont, TBox: UiBox, TParent: PassiveView> PerfOverlayView<'a, TFont, TBox, TParent> {
+    pub fn new(
+        ui_font: &'a TFont,
+        ui_box: &'a TBox,
+        toggle_key: KeyCode,
+        bg_color: (u8, u8, u8),
+        coords: (int, int),
+        text_gap: uint,
+        parent: &'a mut TParent)
+            -> PerfOverlayView<'a, TFont, TBox, TParent> {
+        PerfOverlayView {
+            tracker: None,
+            toggle_key: toggle_key,
+            visible: true,
+            bg_color: bg_color,
+            coords: coords,
+            text_gap: text_gap,
+            graph_height: 32,
+            ui_font: ui_font,
+            ui_box: ui_box,
+            parent: parent
+        }
+    }
+
+    pub fn is_visible(&self) -> bool { self.visible }
+    pub fn set_visible(&mut self, visible: bool) { self.visible = visible; }
+
+    fn draw_overlay(&self, ctx: &ViewContext, tracker: &TimeTracker) -> P2duxResult<()> {
+        let display = ctx.get_display();
+        let lines = vec![
+            format!("FPS {}", tracker.get_smoothed_fps() as uint),
+            format!("MS {} MAX {}", tracker.get_ms_since(),
+                    tracker.get_max_frame_time()),
+            format!("TILES {}", display.get_last_stats().total_tile_copies()),
+            format!("SHEETS {}", display.get_sheet_count())];
+        let box_size = try!(compute_text_box_bounds(
+            lines.as_slice(), self.ui_font, self.ui_box, self.text_gap));
+        try!(draw_text_box(display, self.coords, box_size, self.bg_color,
+                           lines.as_slice(), self.ui_font, self.ui_box, self.text_gap));
+
+        // frame time graph, one 2px bar per frame, directly under the box;
+        // the scale tops out at two frames' worth of 60fps
+        let (box_x, box_y) = self.coords;
+        let (box_w, box_h) = box_size;
+        let unit = self.ui_box.unit_size();
+        let graph_y = box_y + (box_h * unit) as int;
+        let graph_h = self.graph_height as int;
+        let graph_w = (box_w * unit) as int;
+        let _layer = display.push_layer(self.ui_box.get_layer());
+        try!(display.set_draw_color(self.bg_color));
+        try!(display.fill_rect(&Rect::new(box_x as i32, graph_y as i32, graph_w as i32, graph_h as i32)));
+        let frame_times = tracker.get_frame_times();
+        let bar_count = min(frame_times.len(), (graph_w / 2) as uint);
+        let recent = frame_times.slice_from(frame_times.len() - bar_count);
+        for (i, ft) in recent.iter().enumerate() {
+            let color = if *ft <= 17 { Color::RGB(0, 200, 0) }
+                else if *ft <= 33 { Color::RGB(220, 200, 0) }
+                else { Color::RGB(220, 0, 0) };
+            let bar_h = max(1, min(graph_h, (*ft as int * graph_h) / 34));
+            try!(display.set_draw_sdl2_color(color));
+            try!(display.fill_rect(&Rect::new((box_x + i as int * 2) as i32,
+                                              (graph_y + graph_h - bar_h) as i32,
+                                              2, bar_h as i32)));
+        }
+        Ok(())
+    }
+}
+
+impl<'a, TFont: UiFont, TBox: UiBox, TParent: PassiveView>
+        PassiveView for PerfOverlayView<'a, TFont, TBox, TParent> {
+    fn my_passive(&mut self, ctx: &ViewContext, time: u64) -> P2duxResult<()> {
+        self.parent.my_passive(ctx, time)
+    }
+    fn on_resize(&mut self, ctx: &ViewContext, size: (int, int)) -> P2duxResult<()> {
+        self.parent.on_resize(ctx, size)
+    }
+}
+
+impl<'a, TOut, TFont: UiFont, TBox: UiBox, TParent: View<TOut>>
+        View<TOut> for PerfOverlayView<'a, TFont, TBox, TParent> {
+    fn get_parent(&mut self) -> Option<&mut PassiveView> {
+        Some(&mut *self.parent as &mut PassiveView)
+    }
+    fn my_fixed_update(&mut self, ctx: &ViewContext, step_ms: u64) -> P2duxResult<()> {
+        self.parent.my_fixed_update(ctx, step_ms)
+    }
+    fn my_active(&mut self, ctx: &ViewContext, events: &[Event], time: u64) -> P2duxResult<Option<TOut>> {
+        let out = try!(self.parent.my_active(ctx, events, time));
+        match self.tracker {
+            Some(ref mut tracker) => tracker.update(),
+            None => {}
+        }
+        if self.tracker.is_none() {
+            self.tracker = Some(TimeTracker::with_clock(ctx.get_clock(), DEFAULT_FRAME_HISTORY));
+        }
+        for event in events.iter() {
+            match *event {
+                KeyDown(_, _, key, _, _, _) if key == self.toggle_key => {
+                    self.visible = !self.visible;
+                },
+                _ => {}
+            }
+        }
+        if self.visible {
+            match self.tracker {
+                // a debug overlay that can't be drawn (say, a missing glyph)
+                // shouldn't end the view it's watching
+                Some(ref tracker) => { let _ = self.draw_overlay(ctx, tracker); },
+                None => {}
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl<'a, TBox: UiBox> LoadingScreenView<'a, TBox> {
+    pub fn new(
+        loader: SheetLoader,
+        ui_box: &'a TBox,
+        bg_color: (u8, u8, u8),
+        bar_color: (u8, u8, u8),
+        coords: (int, int),
+        box_size: (uint, uint))
+            -> LoadingScreenView<'a, TBox> {
+        LoadingScreenView {
+            loader: loader,
+            uploads_per_frame: 1,
+            bg_color: bg_color,
+            bar_color: bar_color,
+            coords: coords,
+            box_size: box_size,
+            ui_box: ui_box
+        }
+    }
+
+    pub fn set_uploads_per_frame(&mut self, count: uint) { self.uploads_per_frame = max(1, count); }
+    pub fn get_failed<'b>(&'b self) -> &'b [(String, P2duxError)] { self.loader.get_failed() }
+
+    fn draw_progress(&self, ctx: &ViewContext) -> P2duxResult<()> {
+        let display = ctx.get_display();
+        try!(self.ui_box.draw_box(display, self.coords, self.box_size, self.bg_color));
+        // the bar fills the inside of the box, one unit in from each edge
+        let unit = self.ui_box.unit_size() as int;
+        let (box_x, box_y) = self.coords;
+        let (box_w, box_h) = self.box_size;
+        let inner_w = max(0, box_w as int - 2) * unit;
+        let inner_h = max(0, box_h as int - 2) * unit;
+        let bar_w = (inner_w as f64 * self.loader.get_progress()) as int;
+        if bar_w > 0 && inner_h > 0 {
+            let _layer = display.push_layer(self.ui_box.get_layer());
+            try!(display.set_draw_color(self.bar_color));
+            try!(display.fill_rect(&Rect::new((box_x + unit) as i32, (box_y + unit) as i32,
+                                              bar_w as i32, inner_h as i32)));
+        }
+        Ok(())
+    }
+}
+
+impl<'a, TBox: UiBox> PassiveView for LoadingScreenView<'a, TBox> {
+    fn my_passive(&mut self, ctx: &ViewContext, _time: u64) -> P2duxResult<()> {
+        let display = ctx.get_display();
+        try!(display.set_draw_color(self.bg_color));
+        match display.renderer.clear() {
+            Err(e) => Err(P2duxError::RenderCall(
+                format!("LoadingScreenView.my_passive(): failed to clear display: {}", e))),
+            _ => Ok(())
+        }
+    }
+}
+
+impl<'a, TBox: UiBox> View<Vec<SheetHandle>> for LoadingScreenView<'a, TBox> {
+    fn get_parent<'a>(&'a mut self) -> Option<&'a mut PassiveView> { None }
+    fn my_active(&mut self, ctx: &ViewContext, _events: &[Event], _time: u64) -> P2duxResult<Option<Vec<SheetHandle>>> {
+        try!(self.loader.upload_ready(ctx.get_display(), self.uploads_per_frame));
+        try!(self.draw_progress(ctx));
+        if self.loader.is_done() {
+            Ok(Some(self.loader.take_handles()))
+        } else {
+            Ok(None)
         }
-        draw_text_box(ctx.get_display(), self.coords, self.box_size, self.bg_color, self.box_content.as_slice(), self.ui_font,
-                      self.ui_box, self.text_gap);
-        out
     }
 }
 
@@ -143,15 +383,147 @@ impl DisplayClearerView {
     }
 }
 
-impl View for DisplayClearerView {
-    fn get_parent<'a>(&'a mut self) -> Option<&'a mut View> { None }
-    fn my_active(&mut self, ctx: &ViewContext, events: &[Event], time: u64) -> Option<Box<Any>> { None }
-    fn my_passive(&mut self, ctx: &ViewContext, _time: u64) {
+impl<TOut> View<TOut> for DisplayClearerView {
+    fn get_parent<'a>(&'a mut self) -> Option<&'a mut PassiveView> { None }
+    fn my_active(&mut self, ctx: &ViewContext, events: &[Event], time: u64) -> P2duxResult<Option<TOut>> { Ok(None) }
+}
+
+impl PassiveView for DisplayClearerView {
+    fn my_passive(&mut self, ctx: &ViewContext, _time: u64) -> P2duxResult<()> {
         let display = ctx.get_display();
-        display.set_draw_color(self.bg_color);
+        try!(display.set_draw_color(self.bg_color));
         match display.renderer.clear() {
-            Err(e) => panic!("Display Clearer.update(): failed to clear display: {}", e),
-            _ => {}
+            Err(e) => Err(P2duxError::RenderCall(
+                format!("DisplayClearerView.my_passive(): failed to clear display: {}", e))),
+            _ => Ok(())
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::vec::Vec;
+    use sdl2::event::Event;
+    use sdl2::event::Event::{TextInput, KeyDown};
+    use sdl2::keycode::KeyCode;
+    use sdl2::scancode::ScanCode;
+    use sdl2::keyboard::Mod;
+    use sdl2::video::{Window, WindowPos, WindowFlags};
+    use p2d::sprite::SpriteTile;
+
+    use gfx::GameDisplay;
+    use gfx::snapshot::Frame;
+    use gfx::texture::TextureSheet;
+    use ui::{UiFont, UiBox};
+    use error::{P2duxError, P2duxResult};
+    use view::{View, ViewContext};
+    use view::input::ScriptedFrame;
+    use super::{TextInputDialogView, TextInputResult, DisplayClearerView};
+
+    // every glyph and box piece is the same solid 8x8 tile
+    struct TestFont { tile: SpriteTile }
+    struct TestBox { tile: SpriteTile }
+
+    fn tile() -> SpriteTile {
+        SpriteTile { sheet: "test".to_string(), coords: (0, 0), size: (8, 8) }
+    }
+
+    impl UiFont for TestFont {
+        fn get_sheet(&self) -> String { "test".to_string() }
+        fn sprite_for<'a>(&'a self, _c: &char) -> Option<&'a SpriteTile> { Some(&self.tile) }
+    }
+
+    impl UiBox for TestBox {
+        fn unit_size(&self) -> uint { 8 }
+        fn get_sheet(&self) -> String { "test".to_string() }
+        fn get_ul_corner<'a>(&'a self) -> &'a SpriteTile { &self.tile }
+        fn get_ur_corner<'a>(&'a self) -> &'a SpriteTile { &self.tile }
+        fn get_ll_corner<'a>(&'a self) -> &'a SpriteTile { &self.tile }
+        fn get_lr_corner<'a>(&'a self) -> &'a SpriteTile { &self.tile }
+        fn get_top<'a>(&'a self) -> &'a SpriteTile { &self.tile }
+        fn get_bottom<'a>(&'a self) -> &'a SpriteTile { &self.tile }
+        fn get_left<'a>(&'a self) -> &'a SpriteTile { &self.tile }
+        fn get_right<'a>(&'a self) -> &'a SpriteTile { &self.tile }
+    }
+
+    fn headless_display() -> GameDisplay {
+        let display = GameDisplay::new_headless((320, 240), Vec::new()).unwrap();
+        let frame = Frame::new(8, 8, Vec::from_elem(8 * 8 * 4, 255u8));
+        let sheet = TextureSheet::from_surface(
+            &*display.renderer, frame.to_surface().unwrap(),
+            &"test.png".to_string(), "test".to_string(), false).unwrap();
+        display.add_sheet(sheet);
+        display
+    }
+
+    // events carry a window; the dummy driver is happy to make one
+    fn test_window() -> Window {
+        Window::new("p2dux test", WindowPos::PosUndefined, WindowPos::PosUndefined,
+                    8, 8, WindowFlags::empty()).unwrap()
+    }
+
+    fn text(window: &Window, txt: &str) -> Event {
+        TextInput(0, Window::from_id(window.get_id()).unwrap(), txt.to_string())
+    }
+
+    fn key(window: &Window, key: KeyCode) -> Event {
+        KeyDown(0, Window::from_id(window.get_id()).unwrap(), key,
+                ScanCode::Unknown, Mod::empty(), false)
+    }
+
+    // runs a name prompt over a cleared screen, one scripted frame per
+    // entry of `frames`, 16ms apart
+    fn run_dialog(display: GameDisplay, frames: Vec<Vec<Event>>) -> P2duxResult<TextInputResult> {
+        let script = frames.into_iter().enumerate()
+            .map(|(i, events)| ScriptedFrame::new((i as u64 + 1) * 16, events)).collect();
+        let ctx = ViewContext::new_scripted(display, script);
+        let font = TestFont { tile: tile() };
+        let ui_box = TestBox { tile: tile() };
+        let preface = vec!["WHO ARE YOU".to_string()];
+        let mut parent = DisplayClearerView::new((0, 0, 0));
+        let mut dialog = try!(TextInputDialogView::new(
+            &font, &ui_box, None, preface.as_slice(), "NAME".to_string(), "_".to_string(),
+            (0, 0, 0), (8, 8), 1, &mut parent));
+        dialog.enter(&ctx)
+    }
+
+    #[test]
+    fn typing_then_return_submits() {
+        let display = headless_display();
+        let w = test_window();
+        let frames = vec![
+            vec![text(&w, "a")],
+            vec![text(&w, "b"), text(&w, "c")],
+            vec![key(&w, KeyCode::Return)]];
+        assert_eq!(run_dialog(display, frames), Ok(TextInputResult::Submitted("abc".to_string())));
+    }
+
+    #[test]
+    fn backspace_removes_last_char() {
+        let display = headless_display();
+        let w = test_window();
+        let frames = vec![
+            vec![text(&w, "abx")],
+            vec![key(&w, KeyCode::Backspace)],
+            vec![key(&w, KeyCode::Return)]];
+        assert_eq!(run_dialog(display, frames), Ok(TextInputResult::Submitted("ab".to_string())));
+    }
+
+    #[test]
+    fn escape_cancels() {
+        let display = headless_display();
+        let w = test_window();
+        let frames = vec![
+            vec![text(&w, "abc")],
+            vec![key(&w, KeyCode::Escape)]];
+        assert_eq!(run_dialog(display, frames), Ok(TextInputResult::Cancelled));
+    }
+
+    #[test]
+    fn running_out_of_script_fails() {
+        let display = headless_display();
+        let w = test_window();
+        let frames = vec![vec![text(&w, "abc")], vec![]];
+        assert_eq!(run_dialog(display, frames), Err(P2duxError::InputExhausted));
+    }
+}
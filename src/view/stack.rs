@@ -0,0 +1,95 @@
+// Copyright 2013-2014 Jeffery Olson
+//
+// Licensed under the 3-Clause BSD License, see LICENSE.txt
+// at the top-level of this repository.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::vec::Vec;
+
+use sdl2::event::Event;
+
+use error::P2duxResult;
+use super::{View, PassiveView, ViewContext};
+
+// What a view sitting in a ViewStack asks the stack to do next
+pub enum Transition {
+    Push(Box<View<Transition> + 'static>),
+    Pop,
+    Replace(Box<View<Transition> + 'static>)
+}
+
+// Owns a stack of views and runs them all from a single enter() loop.
+// Every frame each view's passive pass runs bottom to top, so lower views
+// draw beneath the top one, and then only the top view gets my_active().
+// Views in the stack are drawn by the stack itself, so they should return
+// None from get_parent() rather than chaining to the view beneath them.
+// enter() returns once the last view has been popped.
+pub struct ViewStack {
+    views: Vec<Box<View<Transition> + 'static>>
+}
+
+impl ViewStack {
+    pub fn new(root: Box<View<Transition> + 'static>) -> ViewStack {
+        ViewStack { views: vec![root] }
+    }
+
+    pub fn push(&mut self, view: Box<View<Transition> + 'static>) {
+        self.views.push(view);
+    }
+
+    pub fn pop(&mut self) -> Option<Box<View<Transition> + 'static>> {
+        self.views.pop()
+    }
+
+    pub fn len(&self) -> uint { self.views.len() }
+    pub fn is_empty(&self) -> bool { self.views.is_empty() }
+
+    fn apply(&mut self, transition: Transition) {
+        match transition {
+            Transition::Push(view) => self.views.push(view),
+            Transition::Pop => { self.views.pop(); },
+            Transition::Replace(view) => {
+                self.views.pop();
+                self.views.push(view);
+            }
+        }
+    }
+}
+
+impl PassiveView for ViewStack {
+    fn my_passive(&mut self, ctx: &ViewContext, time: u64) -> P2duxResult<()> {
+        for view in self.views.iter_mut() {
+            try!(view.my_passive(ctx, time));
+        }
+        Ok(())
+    }
+    fn on_resize(&mut self, ctx: &ViewContext, size: (int, int)) -> P2duxResult<()> {
+        for view in self.views.iter_mut() {
+            try!(view.on_resize(ctx, size));
+        }
+        Ok(())
+    }
+}
+
+impl View<()> for ViewStack {
+    fn get_parent<'a>(&'a mut self) -> Option<&'a mut PassiveView> { None }
+    fn my_active(&mut self, ctx: &ViewContext, events: &[Event], time: u64) -> P2duxResult<Option<()>> {
+        let transition = match self.views.last_mut() {
+            Some(top) => try!(top.my_active(ctx, events, time)),
+            None => return Ok(Some(()))
+        };
+        match transition {
+            Some(t) => self.apply(t),
+            None => {}
+        }
+        Ok(if self.views.is_empty() { Some(()) } else { None })
+    }
+    // only the top view advances; anything beneath it is effectively paused
+    fn my_fixed_update(&mut self, ctx: &ViewContext, step_ms: u64) -> P2duxResult<()> {
+        match self.views.last_mut() {
+            Some(top) => top.my_fixed_update(ctx, step_ms),
+            None => Ok(())
+        }
+    }
+}